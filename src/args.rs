@@ -0,0 +1,176 @@
+//! Command line argument parsing
+
+use crate::{
+    hosts::HostsCommand,
+    link::ConnectionLink,
+    settings::{RedirectMode, UpdateBehavior},
+};
+use log::LevelFilter;
+use std::{env::args_os, ffi::OsString, path::PathBuf, str::FromStr};
+use thiserror::Error;
+
+/// Usage text printed for the `--help` argument
+pub const USAGE: &str = "\
+Usage: pocket-relay-client [OPTIONS] [LINK]
+       pocket-relay-client hosts <apply|remove|status>
+       pocket-relay-client hosts-helper [--exit-when-idle]
+       pocket-relay-client doctor
+
+Arguments:
+  [LINK]                Connection link (pocketrelay://connect?url=...)
+
+Commands:
+  hosts apply           Add the redirect to the hosts file and leave it applied
+  hosts remove          Remove the redirect from the hosts file
+  hosts status          Print the redirect entries in the hosts file, exits
+                        with 1 when the redirect isn't applied
+  hosts-helper          Run the helper that modifies the hosts file for
+                        clients that aren't running as admin (Requires admin)
+  doctor                Check for common connection problems, exits with 1
+                        when any of the checks fail
+
+Options:
+  --connect <URL>       Connection URL to use instead of the saved profiles
+  --identity <PATH>     Path to a client identity file (.p12 / .pfx)
+  --config <PATH>       Path to the config file
+  --log-level <LEVEL>   Log level (off, error, warn, info, debug, trace)
+  --update <BEHAVIOR>   Update behavior (prompt, auto, off)
+  --portable            Store the config file next to the executable
+  --headless            Run without a GUI printing the status to the terminal
+  --control-api         Enable the local control API
+  --exit-when-idle      Stop the hosts helper once its last client disconnects
+  --redirect-mode <MODE>
+                        Redirect using the hosts file or the built-in DNS
+                        server (hosts, dns)
+  -h, --help            Print this help text
+  -V, --version         Print the client version
+
+Each option can also be set using the POCKET_RELAY_* environment variables";
+
+/// Arguments provided on the command line, values that weren't
+/// provided are left as [`None`]
+#[derive(Debug, Default)]
+pub struct Args {
+    /// Connection URL to use
+    pub connection_url: Option<String>,
+    /// Connection link to use
+    pub link: Option<ConnectionLink>,
+    /// Hosts file command to run instead of the client
+    pub hosts: Option<HostsCommand>,
+    /// Whether to run the hosts helper instead of the client
+    pub hosts_helper: bool,
+    /// Whether the hosts helper stops once its last client disconnects
+    pub exit_when_idle: bool,
+    /// Whether to run the diagnostics instead of the client
+    pub doctor: bool,
+    /// Path to the client identity file
+    pub identity_path: Option<PathBuf>,
+    /// Path to the config file
+    pub config_path: Option<PathBuf>,
+    /// Log level to use
+    pub log_level: Option<LevelFilter>,
+    /// Update behavior to use
+    pub update: Option<UpdateBehavior>,
+    /// Whether portable mode is enabled
+    pub portable: bool,
+    /// Whether to run without a GUI
+    pub headless: bool,
+    /// Whether to enable the local control API
+    pub control_api: bool,
+    /// Redirect mode to use
+    pub redirect_mode: Option<RedirectMode>,
+    /// Whether the help text should be printed
+    pub help: bool,
+    /// Whether the version should be printed
+    pub version: bool,
+}
+
+/// Errors that could occur while parsing arguments
+#[derive(Debug, Error)]
+pub enum ArgsError {
+    /// Argument wasn't valid unicode
+    #[error("Argument was not valid unicode: {0:?}")]
+    NonUnicode(OsString),
+    /// Argument isn't known
+    #[error("Unknown argument: {0}")]
+    Unknown(String),
+    /// Argument requires a value but none was provided
+    #[error("Missing value for argument: {0}")]
+    MissingValue(String),
+    /// Argument value couldn't be parsed
+    #[error("Invalid value for argument {0}: {1}")]
+    InvalidValue(String, String),
+}
+
+/// Parses the [`Args`] from the process command line arguments
+pub fn parse_args() -> Result<Args, ArgsError> {
+    let args = args_os()
+        .skip(1)
+        .map(|arg| arg.into_string().map_err(ArgsError::NonUnicode))
+        .collect::<Result<Vec<String>, ArgsError>>()?;
+    parse(args)
+}
+
+/// Parses the [`Args`] from the provided list of `args`
+///
+/// ## Arguments
+/// * `args` - The arguments excluding the program name
+fn parse(args: Vec<String>) -> Result<Args, ArgsError> {
+    let mut output = Args::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        // Split inline values (--name=value)
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => {
+                (name.to_string(), Some(value.to_string()))
+            }
+            _ => (arg, None),
+        };
+
+        // Takes the inline value or the next argument as the value
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| ArgsError::MissingValue(name.clone()))
+        };
+
+        match name.as_str() {
+            "--connect" => output.connection_url = Some(value()?),
+            "--identity" => output.identity_path = Some(PathBuf::from(value()?)),
+            "--config" => output.config_path = Some(PathBuf::from(value()?)),
+            "--log-level" => output.log_level = Some(parse_value(&name, &value()?)?),
+            "--update" => output.update = Some(parse_value(&name, &value()?)?),
+            "--portable" => output.portable = true,
+            "--headless" => output.headless = true,
+            "--control-api" => output.control_api = true,
+            "--exit-when-idle" => output.exit_when_idle = true,
+            "--redirect-mode" => output.redirect_mode = Some(parse_value(&name, &value()?)?),
+            "-h" | "--help" => output.help = true,
+            "-V" | "--version" => output.version = true,
+            "hosts" => output.hosts = Some(parse_value(&name, &value()?)?),
+            "hosts-helper" => output.hosts_helper = true,
+            "doctor" => output.doctor = true,
+            _ if ConnectionLink::is_link(&name) => output.link = Some(parse_value("LINK", &name)?),
+            _ => return Err(ArgsError::Unknown(name)),
+        }
+    }
+
+    Ok(output)
+}
+
+/// Parses the `value` for the argument `name`
+///
+/// ## Arguments
+/// * `name`  - The name of the argument
+/// * `value` - The value to parse
+fn parse_value<T>(name: &str, value: &str) -> Result<T, ArgsError>
+where
+    T: FromStr,
+    T::Err: ToString,
+{
+    value
+        .parse()
+        .map_err(|err: T::Err| ArgsError::InvalidValue(name.to_string(), err.to_string()))
+}
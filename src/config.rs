@@ -1,20 +1,370 @@
-use crate::ui::show_error;
-use log::debug;
+use crate::{
+    files::{with_suffix, write_atomic},
+    hosts::Redirect,
+    settings::{RedirectMode, TunnelMode, UpdateBehavior},
+    ui::{show_error, show_warning},
+};
+use log::{debug, error, warn, LevelFilter};
 use serde::{Deserialize, Serialize};
-use std::{env::current_exe, path::PathBuf};
+use serde_json::{json, Map, Value};
+use std::{
+    env::{current_exe, var_os},
+    fmt::Display,
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
 
 /// Name of the file that stores saved pocket relay configuration info
 pub const CONFIG_FILE_NAME: &str = "pocket-relay-client.json";
+/// Name of the directory within the user config directory that stores
+/// the config file
+pub const CONFIG_DIR_NAME: &str = "pocket-relay";
+/// Name of the marker file that enables portable mode when placed next
+/// to the executable
+pub const PORTABLE_MARKER_NAME: &str = "pocket-relay-portable";
+/// Suffix appended to the config file name for the backup of the last
+/// valid config file
+pub const BACKUP_SUFFIX: &str = ".backup";
+/// Interval between checks for changes to the config file
+#[cfg(any(feature = "iced", feature = "native"))]
+pub const CONFIG_WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Resolved path to the config file
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+/// Whether the config file was created by a newer version of the client,
+/// the config file isn't written to when set so its not overwritten
+static CONFIG_LOCKED: AtomicBool = AtomicBool::new(false);
+/// Contents of the config file when it was loaded at startup, used as
+/// the initial contents when watching the config file for changes
+static LOADED_CONFIG: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Current version of the config file format, this must be incremented
+/// and a migration added to [`MIGRATIONS`] when the format changes
+pub const CONFIG_VERSION: u32 = 2;
 
 /// Structure of the configuration file
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct ClientConfig {
-    /// The saved connection URL to use
+    /// Version of the config file format
+    pub version: u32,
+    /// The saved server profiles
+    pub profiles: Vec<ServerProfile>,
+    /// Path to the client identity file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity_path: Option<PathBuf>,
+    /// Log level to use
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<LevelFilter>,
+    /// Update behavior to use
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update: Option<UpdateBehavior>,
+    /// Tunnel mode to use
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tunnel: Option<TunnelMode>,
+    /// Whether to reconnect when the URL of the connected profile is
+    /// changed while the client is running
+    #[serde(default)]
+    pub reconnect_on_change: bool,
+    /// Whether to enable the local control API
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub control_api: Option<bool>,
+    /// Host names to redirect in the hosts file and the addresses to
+    /// redirect them to, replaces the default redirect when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redirects: Option<Vec<Redirect>>,
+    /// Method used to apply the redirects
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redirect_mode: Option<RedirectMode>,
+    /// Address for the DNS server to listen on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns_address: Option<SocketAddr>,
+    /// Upstream DNS server to forward queries to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns_upstream: Option<SocketAddr>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            profiles: Vec::new(),
+            identity_path: None,
+            log_level: None,
+            update: None,
+            tunnel: None,
+            reconnect_on_change: false,
+            control_api: None,
+            redirects: None,
+            redirect_mode: None,
+            dns_address: None,
+            dns_upstream: None,
+        }
+    }
+}
+
+/// Saved connection details for a server
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ServerProfile {
+    /// Display name for the profile
+    pub name: String,
+    /// The connection URL for the server
     pub connection_url: String,
+    /// Unix timestamp (seconds) of when the profile was last used
+    #[serde(default)]
+    pub last_used: Option<u64>,
+    /// Whether the profile has been marked as a favourite
+    #[serde(default)]
+    pub favourite: bool,
+}
+
+impl Display for ServerProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.favourite {
+            write!(f, "★ {}", self.name)
+        } else {
+            self.name.fmt(f)
+        }
+    }
+}
+
+/// Errors that could occur while loading the config file
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    /// Failed to read the config file
+    #[error("Failed to read config file: {0}")]
+    Read(#[from] io::Error),
+    /// Config file was not valid JSON or didn't match the expected structure
+    #[error("Invalid config file: {0}")]
+    Parse(#[from] serde_json::Error),
+    /// Config file was created by a newer version of the client
+    #[error("Config file version {0} is newer than the supported version {CONFIG_VERSION}")]
+    UnsupportedVersion(u64),
+    /// Config file content couldn't be migrated to the current version
+    #[error("Failed to migrate config file from version {0}: {1}")]
+    Migration(u32, &'static str),
+}
+
+/// Function that upgrades the raw config value from one version to the next
+type Migration = fn(&mut Map<String, Value>) -> Result<(), &'static str>;
+
+/// Migrations for upgrading older config files, the migration at index N
+/// upgrades a config from version N + 1 to version N + 2
+const MIGRATIONS: [Migration; (CONFIG_VERSION - 1) as usize] = [migrate_v1_to_v2];
+
+/// Version 1 config files only stored a single `connection_url` field
+/// which is converted into a single saved profile
+fn migrate_v1_to_v2(value: &mut Map<String, Value>) -> Result<(), &'static str> {
+    let profiles = match value.remove("connection_url") {
+        Some(Value::String(connection_url)) => vec![json!({
+            "name": profile_name(&connection_url),
+            "connection_url": connection_url,
+        })],
+        Some(_) => return Err("connection_url was not a string"),
+        None => Vec::new(),
+    };
+
+    value.insert("profiles".to_string(), Value::Array(profiles));
+    Ok(())
+}
+
+/// Parses the config file `bytes` applying any migrations required
+/// to bring the config up to the current version
+///
+/// ## Arguments
+/// * `bytes` - The raw config file bytes
+pub fn parse_config(bytes: &[u8]) -> Result<ClientConfig, ConfigError> {
+    let mut value: Map<String, Value> = serde_json::from_slice(bytes)?;
+
+    // Config files without a version predate versioning (Version 1)
+    let version = match value.get("version") {
+        Some(version) => version
+            .as_u64()
+            .ok_or(ConfigError::Migration(0, "version was not a number"))?,
+        None => 1,
+    };
+
+    if version == 0 {
+        return Err(ConfigError::Migration(0, "version must be at least 1"));
+    }
+
+    if version > CONFIG_VERSION as u64 {
+        return Err(ConfigError::UnsupportedVersion(version));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize - 1) {
+        let from = index as u32 + 1;
+        debug!("Migrating config file from version {}", from);
+        migration(&mut value).map_err(|err| ConfigError::Migration(from, err))?;
+    }
+
+    value.insert("version".to_string(), Value::from(CONFIG_VERSION));
+
+    let config = serde_json::from_value(Value::Object(value))?;
+    Ok(config)
+}
+
+impl ClientConfig {
+    /// Adds a profile for the provided `connection_url` or updates the
+    /// last used time of the existing profile for the URL. Returns the
+    /// index of the profile
+    ///
+    /// ## Arguments
+    /// * `connection_url` - The connection URL to save
+    pub fn save_profile(&mut self, connection_url: &str) -> usize {
+        let last_used = Some(unix_timestamp());
+
+        if let Some(index) = self.find_profile(connection_url) {
+            self.profiles[index].last_used = last_used;
+            return index;
+        }
+
+        self.profiles.push(ServerProfile {
+            name: profile_name(connection_url),
+            connection_url: connection_url.to_string(),
+            last_used,
+            favourite: false,
+        });
+        self.profiles.len() - 1
+    }
+
+    /// Adds a profile for the provided `connection_url` using `name` as the
+    /// display name, renames the existing profile for the URL if present.
+    /// Returns the index of the profile
+    ///
+    /// ## Arguments
+    /// * `connection_url` - The connection URL to save
+    /// * `name`           - Optional display name for the profile
+    pub fn add_profile(&mut self, connection_url: &str, name: Option<String>) -> usize {
+        let index = self.save_profile(connection_url);
+        if let Some(name) = name {
+            self.profiles[index].name = name;
+        }
+        index
+    }
+
+    /// Finds the index of the profile using the provided `connection_url`
+    ///
+    /// ## Arguments
+    /// * `connection_url` - The connection URL to find
+    pub fn find_profile(&self, connection_url: &str) -> Option<usize> {
+        let connection_url = normalize_url(connection_url);
+        self.profiles
+            .iter()
+            .position(|profile| normalize_url(&profile.connection_url) == connection_url)
+    }
+
+    /// Provides the most recently used profile if there are any
+    pub fn last_used_profile(&self) -> Option<&ServerProfile> {
+        self.profiles
+            .iter()
+            .max_by_key(|profile| profile.last_used.unwrap_or_default())
+    }
+
+    /// Provides the new connection URL for the profile that `target` belonged
+    /// to in the `previous` config if the URL of the profile was changed. The
+    /// profile is matched by name so that edits to its URL are detected
+    ///
+    /// ## Arguments
+    /// * `previous` - The config before it was reloaded
+    /// * `target`   - The current connection URL
+    #[cfg(any(feature = "iced", feature = "native"))]
+    pub fn changed_profile_url(&self, previous: &ClientConfig, target: &str) -> Option<&str> {
+        let previous = &previous.profiles[previous.find_profile(target)?];
+        let current = self
+            .profiles
+            .iter()
+            .find(|profile| profile.name == previous.name)?;
+
+        if normalize_url(&current.connection_url) == normalize_url(target) {
+            return None;
+        }
+
+        Some(&current.connection_url)
+    }
+
+    /// Sorts the profiles so that favourites come first followed by the
+    /// most recently used profiles
+    pub fn sort_profiles(&mut self) {
+        self.profiles.sort_by(|a, b| {
+            b.favourite
+                .cmp(&a.favourite)
+                .then_with(|| b.last_used.cmp(&a.last_used))
+        });
+    }
+}
+
+/// Normalizes a connection URL for comparison, the lookup process appends
+/// a scheme and trailing slash so these are ignored
+pub fn normalize_url(value: &str) -> &str {
+    let value = value.trim();
+    let value = value
+        .strip_prefix("http://")
+        .or_else(|| value.strip_prefix("https://"))
+        .unwrap_or(value);
+    value.trim_end_matches('/')
+}
+
+/// Creates a display name for a profile from its `connection_url`
+fn profile_name(connection_url: &str) -> String {
+    normalize_url(connection_url).to_string()
+}
+
+/// Provides the current time as seconds since the unix epoch
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_secs())
+        .unwrap_or_default()
+}
+
+/// Sets the path to the configuration file, must be called before the
+/// config path is first used
+///
+/// ## Arguments
+/// * `path`     - Explicit path to use for the config file
+/// * `portable` - Whether portable mode is enabled
+pub fn init_config_path(path: Option<PathBuf>, portable: bool) {
+    let path = path.unwrap_or_else(|| default_config_path(portable));
+    if CONFIG_PATH.set(path).is_err() {
+        warn!("Config path was used before it was initialized");
+    }
+}
+
+/// Provides a [`Path`] to the configuration file
+pub fn config_path() -> &'static Path {
+    CONFIG_PATH.get_or_init(|| default_config_path(false))
+}
+
+/// Provides the default config file path, uses the portable location
+/// when `portable` is set or the portable marker file is present
+///
+/// ## Arguments
+/// * `portable` - Whether portable mode is enabled
+fn default_config_path(portable: bool) -> PathBuf {
+    if portable || is_portable() {
+        debug!("Using portable config location");
+        return portable_config_path();
+    }
+
+    match user_config_dir() {
+        Some(dir) => dir.join(CONFIG_DIR_NAME).join(CONFIG_FILE_NAME),
+        None => {
+            warn!("Unable to determine user config directory, using portable config location");
+            portable_config_path()
+        }
+    }
 }
 
-/// Provides a [`PathBuf`] to the configuration file
-pub fn config_path() -> PathBuf {
+/// Provides the [`PathBuf`] to the config file next to the executable,
+/// this is the config location used in portable mode
+fn portable_config_path() -> PathBuf {
     let current_path = current_exe().expect("Failed to find exe path");
     let parent = current_path
         .parent()
@@ -22,40 +372,227 @@ pub fn config_path() -> PathBuf {
     parent.join(CONFIG_FILE_NAME)
 }
 
-/// Reads the [`ClientConfig`] from the config file if one is present
+/// Checks whether the portable mode marker file is present next to
+/// the executable
+fn is_portable() -> bool {
+    portable_config_path()
+        .with_file_name(PORTABLE_MARKER_NAME)
+        .exists()
+}
+
+/// Provides the per-user config directory following the XDG base
+/// directory specification
+#[cfg(target_family = "unix")]
+pub fn user_config_dir() -> Option<PathBuf> {
+    // Relative paths must be ignored according to the specification
+    var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| {
+            var_os("HOME")
+                .map(PathBuf::from)
+                .filter(|path| path.is_absolute())
+                .map(|home| home.join(".config"))
+        })
+}
+
+/// Provides the per-user config directory (%APPDATA%)
+#[cfg(target_family = "windows")]
+pub fn user_config_dir() -> Option<PathBuf> {
+    var_os("APPDATA")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
+
+/// Moves a config file from the portable location next to the executable
+/// into the per-user config location if the per-user config doesn't
+/// exist yet
+pub fn migrate_portable_config() {
+    let file_path = config_path();
+    let portable_path = portable_config_path();
+
+    if file_path == portable_path || file_path.exists() || !portable_path.exists() {
+        return;
+    }
+
+    debug!(
+        "Migrating config file from {} to {}",
+        portable_path.display(),
+        file_path.display()
+    );
+
+    let result = file_path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::copy(&portable_path, file_path));
+
+    if let Err(err) = result {
+        error!("Failed to migrate config file: {}", err);
+        return;
+    }
+
+    // The executable directory may be read-only, the copied file prevents
+    // the migration from running again so failing here is fine
+    if let Err(err) = std::fs::remove_file(&portable_path) {
+        warn!("Failed to remove migrated config file: {}", err);
+    }
+}
+
+/// Provides the path to the backup of the last valid config file
+pub fn backup_config_path() -> PathBuf {
+    with_suffix(config_path(), BACKUP_SUFFIX)
+}
+
+/// Reads the [`ClientConfig`] from the config file if one is present,
+/// falls back to the backup config if the config file is missing or
+/// unreadable
 pub fn read_config_file() -> Option<ClientConfig> {
-    // Check that the config file exists
+    // Check that the config file exists, restoring the backup if its missing
     let file_path = config_path();
     if !file_path.exists() {
-        return None;
+        let value = read_backup_config()?;
+        show_warning(
+            "Restored client config",
+            "The config file was missing, the last valid config has been restored from a backup",
+        );
+        return Some(value);
     }
 
     debug!("Reading config file");
 
-    // Read the config bytes from the file
-    let bytes = match std::fs::read(file_path) {
-        Ok(value) => value,
-        Err(err) => {
-            show_error("Failed to read client config", &err.to_string());
-            return None;
-        }
+    let result = std::fs::read(file_path)
+        .map_err(ConfigError::from)
+        .and_then(|bytes| {
+            let result = parse_config(&bytes);
+            let _ = LOADED_CONFIG.set(bytes);
+            result
+        });
+
+    let err = match result {
+        Ok(value) => return Some(value),
+        Err(err) => err,
     };
 
-    // Parse the config file bytes
-    match serde_json::from_slice(&bytes) {
-        Ok(value) => Some(value),
+    error!("Failed to load client config: {}", err);
+
+    let mut text = err.to_string();
+
+    // Configs from newer versions are left untouched, falling back to the
+    // backup would overwrite the newer config the next time its saved
+    if let ConfigError::UnsupportedVersion(_) = err {
+        CONFIG_LOCKED.store(true, Ordering::SeqCst);
+        text.push_str(
+            "\n\nThe config file was created by a newer version of the client, \
+            it will not be loaded or modified. Update the client to use it",
+        );
+        show_warning("Failed to load client config", &text);
+        return None;
+    }
+
+    // Move the invalid config out of the way so its not overwritten
+    if !matches!(err, ConfigError::Read(_)) {
+        match quarantine_config_file(file_path) {
+            Ok(quarantine_path) => {
+                text.push_str(&format!(
+                    "\n\nThe invalid config file has been moved to {}",
+                    quarantine_path.display()
+                ));
+            }
+            Err(quarantine_err) => {
+                error!("Failed to move invalid config file: {}", quarantine_err);
+            }
+        }
+    }
+
+    // Attempt to recover using the backup of the last valid config
+    if let Some(value) = read_backup_config() {
+        text.push_str("\n\nThe last valid config has been restored from a backup");
+        show_warning("Restored client config", &text);
+        return Some(value);
+    }
+
+    if let ConfigError::Read(_) = err {
+        show_error("Failed to read client config", &text);
+    } else {
+        show_warning("Failed to load client config", &text);
+    }
+
+    None
+}
+
+/// Reads the backup of the last valid config if one is present
+fn read_backup_config() -> Option<ClientConfig> {
+    let backup_path = backup_config_path();
+    if !backup_path.exists() {
+        return None;
+    }
+
+    match read_config(&backup_path) {
+        Ok(value) => {
+            warn!("Restored client config from backup");
+            Some(value)
+        }
         Err(err) => {
-            show_error("Failed to parse client config", &err.to_string());
+            error!("Failed to load backup client config: {}", err);
             None
         }
     }
 }
 
+/// Reads and parses the config file at the provided `path`
+///
+/// ## Arguments
+/// * `path` - The path to the config file
+fn read_config(path: &Path) -> Result<ClientConfig, ConfigError> {
+    let bytes = std::fs::read(path)?;
+    parse_config(&bytes)
+}
+
+/// Moves an invalid config file at `file_path` to a timestamped backup
+/// file next to it, returning the path of the backup file
+///
+/// ## Arguments
+/// * `file_path` - The path to the invalid config file
+fn quarantine_config_file(file_path: &Path) -> io::Result<PathBuf> {
+    let backup_path = with_suffix(file_path, &format!(".{}.bak", unix_timestamp()));
+    std::fs::rename(file_path, &backup_path)?;
+
+    warn!("Moved invalid config file to {}", backup_path.display());
+    Ok(backup_path)
+}
+
+/// Copies the current config file to the backup path if its valid,
+/// invalid config files are moved to a timestamped backup instead so
+/// they aren't lost when the new config is written
+///
+/// ## Arguments
+/// * `file_path` - The path to the current config file
+fn backup_config_file(file_path: &Path) -> io::Result<()> {
+    let bytes = match std::fs::read(file_path) {
+        Ok(value) => value,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    if let Err(err) = parse_config(&bytes) {
+        warn!("Not backing up invalid config file: {}", err);
+        return quarantine_config_file(file_path).map(|_| ());
+    }
+
+    write_atomic(&backup_config_path(), &bytes)
+}
+
 /// Writes the provided `config` to the config file, this will create a new
-/// file if one is not present
-pub fn write_config_file(config: ClientConfig) {
+/// file if one is not present. The previous config is kept as a backup and
+/// the new config is written atomically
+pub fn write_config_file(config: &ClientConfig) {
+    if CONFIG_LOCKED.load(Ordering::SeqCst) {
+        warn!("Not saving client config, the config file is from a newer version");
+        return;
+    }
+
     let file_path = config_path();
-    let bytes = match serde_json::to_vec(&config) {
+    let bytes = match serde_json::to_vec(config) {
         Ok(value) => value,
         Err(err) => {
             show_error("Failed to save client config", &err.to_string());
@@ -63,7 +600,142 @@ pub fn write_config_file(config: ClientConfig) {
         }
     };
     debug!("Writing config file");
-    if let Err(err) = std::fs::write(file_path, bytes) {
+
+    if let Some(parent) = file_path.parent() {
+        if let Err(err) = std::fs::create_dir_all(parent) {
+            show_error("Failed to save client config", &err.to_string());
+            return;
+        }
+    }
+
+    // Keep a backup of the previous config, the config isn't written if
+    // the previous config couldn't be kept
+    if let Err(err) = backup_config_file(file_path) {
+        error!("Failed to backup client config: {}", err);
         show_error("Failed to save client config", &err.to_string());
+        return;
+    }
+
+    if let Err(err) = write_atomic(file_path, &bytes) {
+        show_error("Failed to save client config", &err.to_string());
+    }
+}
+
+/// Watches the config file for changes made while the client is running
+#[cfg(any(feature = "iced", feature = "native"))]
+pub struct ConfigWatcher {
+    /// Contents of the config file from the last check
+    last: Option<Vec<u8>>,
+}
+
+#[cfg(any(feature = "iced", feature = "native"))]
+impl Default for ConfigWatcher {
+    /// Creates a new watcher, changes are detected relative to the config
+    /// file contents that were loaded at startup
+    fn default() -> Self {
+        Self {
+            last: LOADED_CONFIG.get().cloned(),
+        }
+    }
+}
+
+#[cfg(any(feature = "iced", feature = "native"))]
+impl ConfigWatcher {
+    /// Waits for the config file to change, providing the new config once
+    /// its changed to a valid config. Invalid configs are ignored as they
+    /// are likely still being written
+    pub async fn changed(&mut self) -> ClientConfig {
+        loop {
+            tokio::time::sleep(CONFIG_WATCH_INTERVAL).await;
+
+            let bytes = tokio::fs::read(config_path()).await.ok();
+
+            if bytes == self.last {
+                continue;
+            }
+
+            self.last = bytes.clone();
+
+            let Some(bytes) = bytes else { continue };
+
+            match parse_config(&bytes) {
+                Ok(config) => {
+                    debug!("Config file changed");
+                    return config;
+                }
+                Err(err) => debug!("Ignoring invalid config file change: {}", err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrates_v1_config() {
+        let config = parse_config(br#"{"connection_url":"http://127.0.0.1/"}"#).unwrap();
+
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(
+            config.profiles,
+            vec![ServerProfile {
+                name: "127.0.0.1".to_string(),
+                connection_url: "http://127.0.0.1/".to_string(),
+                last_used: None,
+                favourite: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn migrates_empty_v1_config() {
+        let config = parse_config(b"{}").unwrap();
+
+        assert_eq!(config, ClientConfig::default());
+    }
+
+    #[test]
+    fn rejects_invalid_v1_config() {
+        let result = parse_config(br#"{"connection_url":1}"#);
+
+        assert!(matches!(result, Err(ConfigError::Migration(1, _))));
+    }
+
+    #[test]
+    fn loads_current_config() {
+        let config = ClientConfig {
+            profiles: vec![ServerProfile {
+                name: "Server".to_string(),
+                connection_url: "http://127.0.0.1/".to_string(),
+                last_used: Some(1),
+                favourite: true,
+            }],
+            reconnect_on_change: true,
+            ..ClientConfig::default()
+        };
+
+        let bytes = serde_json::to_vec(&config).unwrap();
+
+        assert_eq!(parse_config(&bytes).unwrap(), config);
+    }
+
+    #[test]
+    fn rejects_newer_config() {
+        let bytes = format!(r#"{{"version":{},"profiles":[]}}"#, CONFIG_VERSION + 1);
+        let result = parse_config(bytes.as_bytes());
+
+        assert!(matches!(
+            result,
+            Err(ConfigError::UnsupportedVersion(version)) if version == CONFIG_VERSION as u64 + 1
+        ));
+    }
+
+    #[test]
+    fn rejects_zero_version() {
+        let result = parse_config(br#"{"version":0,"profiles":[]}"#);
+
+        assert!(matches!(result, Err(ConfigError::Migration(0, _))));
     }
 }
@@ -0,0 +1,458 @@
+//! Opt-in local control API allowing other programs (i.e. launchers) to
+//! check the client status and switch servers. Uses a Unix socket on Unix
+//! and a named pipe on Windows that only the current user can access,
+//! requests and responses are single lines of JSON. Connections are closed
+//! after the first line that isn't a valid request
+
+#[cfg(target_family = "unix")]
+use crate::config::{user_config_dir, CONFIG_DIR_NAME};
+use crate::{
+    core::reqwest,
+    history::{HistoryEntry, HistoryOutcome},
+    servers::server_states,
+    settings::{Settings, UpdateBehavior},
+    ui::show_warning,
+    update,
+};
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    io,
+    sync::{Arc, Mutex, RwLock},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    sync::mpsc,
+};
+
+/// Name of the control API socket file
+#[cfg(target_family = "unix")]
+pub const CONTROL_SOCKET_NAME: &str = "pocket-relay-client.sock";
+/// Name of the private directory containing the control API socket
+#[cfg(target_family = "unix")]
+pub const CONTROL_DIR_NAME: &str = "pocket-relay";
+/// Prefix of the control API named pipe name, followed by the security
+/// identifier of the user running the client
+#[cfg(target_family = "windows")]
+pub const CONTROL_PIPE_PREFIX: &str = r"\\.\pipe\pocket-relay-client-";
+
+/// Current connection status
+static CONNECTION_STATUS: Mutex<ConnectionStatus> = Mutex::new(ConnectionStatus::Disconnected);
+/// Settings used by the control API, replaced when the config is reloaded
+static CONTROL_SETTINGS: RwLock<Option<Settings>> = RwLock::new(None);
+
+/// Status of the connection to the server
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ConnectionStatus {
+    /// Not connected to a server
+    Disconnected,
+    /// Looking up the server
+    Connecting { url: String },
+    /// Connected to the server
+    Connected { url: String, version: String },
+    /// Failed to connect to the server
+    Failed { url: String, error: String },
+}
+
+impl From<&HistoryEntry> for ConnectionStatus {
+    fn from(value: &HistoryEntry) -> Self {
+        let url = value.url.clone();
+        match &value.outcome {
+            HistoryOutcome::Success { version } => Self::Connected {
+                url,
+                version: version.clone(),
+            },
+            HistoryOutcome::Failed { error } => Self::Failed {
+                url,
+                error: error.clone(),
+            },
+        }
+    }
+}
+
+/// Sets the current connection status
+///
+/// ## Arguments
+/// * `status` - The new connection status
+pub fn set_connection_status(status: ConnectionStatus) {
+    if let Ok(mut value) = CONNECTION_STATUS.lock() {
+        *value = status;
+    }
+}
+
+/// Sets the settings used by the control API
+///
+/// ## Arguments
+/// * `settings` - The current settings
+pub fn set_control_settings(settings: &Settings) {
+    if let Ok(mut value) = CONTROL_SETTINGS.write() {
+        *value = Some(settings.clone());
+    }
+}
+
+/// Provides the settings used by the control API
+fn control_settings() -> Option<Settings> {
+    CONTROL_SETTINGS.read().ok().and_then(|value| value.clone())
+}
+
+/// Provides the current connection status
+fn connection_status() -> ConnectionStatus {
+    CONNECTION_STATUS
+        .lock()
+        .map(|value| value.clone())
+        .unwrap_or(ConnectionStatus::Disconnected)
+}
+
+/// Commands from the control API that must be handled by the UI
+#[derive(Debug, Clone)]
+pub enum ControlCommand {
+    /// Connect to the provided connection URL
+    Connect(String),
+    /// Stop the local servers
+    Disconnect,
+}
+
+/// Requests that can be sent to the control API
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum ControlRequest {
+    /// Get the connection and server status
+    Status,
+    /// Connect to a server
+    Connect { url: String },
+    /// Stop the local servers
+    Disconnect,
+    /// Check for updates
+    Update,
+}
+
+/// State shared between the control API connections
+struct ControlState {
+    /// HTTP client for update checks
+    http_client: reqwest::Client,
+    /// Sender for commands handled by the UI
+    commands: mpsc::UnboundedSender<ControlCommand>,
+}
+
+/// Runs the control API server, commands that must be handled by the UI
+/// are sent through `commands`
+///
+/// ## Arguments
+/// * `settings`    - The resolved settings
+/// * `http_client` - The HTTP client to use for update checks
+/// * `commands`    - Sender for commands handled by the UI
+pub async fn serve(
+    settings: Settings,
+    http_client: reqwest::Client,
+    commands: mpsc::UnboundedSender<ControlCommand>,
+) {
+    set_control_settings(&settings);
+
+    let state = Arc::new(ControlState {
+        http_client,
+        commands,
+    });
+
+    if let Err(err) = accept_connections(state).await {
+        error!("Failed to start control API: {}", err);
+        show_warning("Failed to start control API", &err.to_string());
+    }
+}
+
+/// Accepts control API connections until the client stops
+///
+/// ## Arguments
+/// * `state` - The shared control API state
+#[cfg(target_family = "unix")]
+async fn accept_connections(state: Arc<ControlState>) -> io::Result<()> {
+    let listener = bind().await?;
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(handle_connection(stream, state.clone()));
+            }
+            Err(err) => error!("Failed to accept control API connection: {}", err),
+        }
+    }
+}
+
+/// Accepts control API connections until the client stops, a new instance
+/// of the named pipe is created for each connection. The pipe can only be
+/// accessed by the current user
+///
+/// ## Arguments
+/// * `state` - The shared control API state
+#[cfg(target_family = "windows")]
+async fn accept_connections(state: Arc<ControlState>) -> io::Result<()> {
+    let name = control_pipe_name()?;
+    let security = crate::pipe::PipeSecurity::current_user()?;
+
+    // Fails if another process already created the pipe
+    let mut server = security.create_pipe(&name, true)?;
+    debug!("Control API listening on {}", name);
+
+    loop {
+        match server.connect().await {
+            Ok(()) => {
+                let stream = std::mem::replace(&mut server, security.create_pipe(&name, false)?);
+                tokio::spawn(handle_connection(stream, state.clone()));
+            }
+            Err(err) => {
+                error!("Failed to accept control API connection: {}", err);
+                server = security.create_pipe(&name, false)?;
+            }
+        }
+    }
+}
+
+/// Binds the control API Unix socket inside a private directory, stale
+/// sockets from clients that didn't exit cleanly are replaced
+#[cfg(target_family = "unix")]
+async fn bind() -> io::Result<tokio::net::UnixListener> {
+    use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+    use tokio::net::{UnixListener, UnixStream};
+
+    let path = control_socket_path()?;
+    if let Some(parent) = path.parent() {
+        create_private_dir(parent)?;
+    }
+
+    if path.exists() {
+        if UnixStream::connect(&path).await.is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is in use by another client", path.display()),
+            ));
+        }
+
+        std::fs::remove_file(&path)?;
+    }
+
+    let listener = UnixListener::bind(&path)?;
+
+    // Only the current user should be able to control the client
+    std::fs::set_permissions(&path, Permissions::from_mode(0o600))?;
+
+    debug!("Control API listening on {}", path.display());
+    Ok(listener)
+}
+
+/// Creates the directory at `path` that only the current user can access,
+/// existing directories must be owned by the current user and must not be
+/// accessible by other users
+///
+/// ## Arguments
+/// * `path` - The path of the directory
+#[cfg(target_family = "unix")]
+fn create_private_dir(path: &std::path::Path) -> io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    match std::fs::DirBuilder::new().mode(0o700).create(path) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(err) => return Err(err),
+    }
+
+    // Symbolic links aren't followed so the directory can't be swapped
+    let metadata = std::fs::symlink_metadata(path)?;
+    if !metadata.is_dir()
+        || metadata.uid() != unsafe { libc::geteuid() }
+        || metadata.mode() & 0o077 != 0
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} must be a directory that only the current user can access",
+                path.display()
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Provides the name of the control API named pipe for the current user
+#[cfg(target_family = "windows")]
+pub fn control_pipe_name() -> io::Result<String> {
+    let sid = crate::pipe::current_user_sid()?;
+    Ok(format!("{CONTROL_PIPE_PREFIX}{sid}"))
+}
+
+/// Provides the path to the control API socket, stored in a private
+/// directory within the user runtime directory or the user config
+/// directory when there isn't a runtime directory
+#[cfg(target_family = "unix")]
+pub fn control_socket_path() -> io::Result<std::path::PathBuf> {
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(std::path::PathBuf::from)
+        .filter(|path| path.is_absolute());
+
+    let dir = match runtime_dir {
+        Some(dir) => dir.join(CONTROL_DIR_NAME),
+        None => user_config_dir()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "Unable to determine the user runtime or config directory",
+                )
+            })?
+            .join(CONFIG_DIR_NAME)
+            .join("run"),
+    };
+
+    Ok(dir.join(CONTROL_SOCKET_NAME))
+}
+
+/// Requests the status from another client running with the control API
+/// enabled, provides [`None`] if no client responded
+pub fn request_client_status() -> Option<Value> {
+    use std::time::Duration;
+
+    /// Time to wait for the other client to respond
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    // The request is sent from another thread as named pipes don't
+    // support read timeouts
+    let (tx, rx) = std::sync::mpsc::channel();
+    let result = std::thread::Builder::new()
+        .name("control-status".to_string())
+        .spawn(move || {
+            let _ = tx.send(request_status());
+        });
+
+    result.ok()?;
+    rx.recv_timeout(TIMEOUT).ok().flatten()
+}
+
+/// Requests the status from another client, provides the status response
+fn request_status() -> Option<Value> {
+    use std::io::{BufRead, Write};
+
+    #[cfg(target_family = "unix")]
+    let stream = control_socket_path().and_then(std::os::unix::net::UnixStream::connect);
+    #[cfg(target_family = "windows")]
+    let stream = control_pipe_name().and_then(|name| {
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(name)
+    });
+
+    let mut stream = stream.ok()?;
+    stream.write_all(b"{\"command\":\"status\"}\n").ok()?;
+
+    let mut line = String::new();
+    std::io::BufReader::new(stream).read_line(&mut line).ok()?;
+
+    serde_json::from_str::<Value>(&line)
+        .ok()
+        .filter(|response| response.get("ok") == Some(&Value::Bool(true)))
+}
+
+/// Handles the requests from a control API connection, the connection is
+/// closed after the first invalid request so requests can't be hidden
+/// within other content (i.e. form submissions from web pages)
+///
+/// ## Arguments
+/// * `stream` - The connection stream
+/// * `state`  - The shared control API state
+async fn handle_connection<S>(stream: S, state: Arc<ControlState>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (read, mut write) = tokio::io::split(stream);
+    let mut lines = BufReader::new(read).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (response, valid) = match serde_json::from_str(&line) {
+            Ok(request) => (handle_request(request, &state), true),
+            Err(err) => (error_response(format!("Invalid request: {err}")), false),
+        };
+
+        let mut response = response.to_string();
+        response.push('\n');
+
+        if write.write_all(response.as_bytes()).await.is_err() || !valid {
+            break;
+        }
+    }
+}
+
+/// Handles a single `request` providing the response
+///
+/// ## Arguments
+/// * `request` - The request to handle
+/// * `state`   - The shared control API state
+fn handle_request(request: ControlRequest, state: &ControlState) -> Value {
+    debug!("Control API request: {:?}", request);
+
+    let Some(settings) = control_settings() else {
+        return error_response("The client settings are unavailable");
+    };
+
+    let command = match request {
+        ControlRequest::Status => return status_response(),
+        ControlRequest::Connect { url } => {
+            if settings.connection_locked {
+                return error_response("The connection URL is locked by the policy");
+            }
+
+            ControlCommand::Connect(url)
+        }
+        ControlRequest::Disconnect => ControlCommand::Disconnect,
+        ControlRequest::Update => {
+            if settings.update == UpdateBehavior::Disabled {
+                return error_response("Updates are disabled");
+            }
+
+            tokio::spawn(update::update(state.http_client.clone(), settings.update));
+            return json!({ "ok": true });
+        }
+    };
+
+    if state.commands.send(command).is_err() {
+        return error_response("The client is shutting down");
+    }
+
+    json!({ "ok": true })
+}
+
+/// Creates the response for the status request
+fn status_response() -> Value {
+    let servers: Vec<Value> = server_states()
+        .into_iter()
+        .map(|(server, state)| {
+            json!({
+                "name": server.name,
+                "protocol": server.protocol,
+                "port": server.port,
+                "state": state,
+            })
+        })
+        .collect();
+
+    json!({
+        "ok": true,
+        "connection": connection_status(),
+        "servers": servers,
+    })
+}
+
+/// Creates an error response
+///
+/// ## Arguments
+/// * `error` - The error message
+fn error_response(error: impl Into<String>) -> Value {
+    json!({ "ok": false, "error": error.into() })
+}
@@ -0,0 +1,458 @@
+//! Local DNS server used as an alternative to modifying the hosts file,
+//! answers queries for the redirected hosts and forwards all other
+//! queries to an upstream DNS server
+
+use crate::{hosts::Redirect, settings::Settings, ui::show_warning};
+use log::{debug, error};
+use std::{
+    io::{self, ErrorKind},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket as StdUdpSocket},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{net::UdpSocket, time::timeout};
+
+/// Default address the DNS server listens on
+pub const DEFAULT_DNS_ADDRESS: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 53);
+/// Upstream DNS server used when one couldn't be found in the system config
+pub const FALLBACK_UPSTREAM: SocketAddr =
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)), 53);
+/// Time to live in seconds for the answers to redirected hosts
+const ANSWER_TTL: u32 = 60;
+/// Time to wait for a response from the upstream server
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(5);
+/// Maximum size of a DNS message over UDP
+const MAX_MESSAGE_SIZE: usize = 4096;
+/// Size of the DNS message header
+const HEADER_SIZE: usize = 12;
+/// Pointer to the name of the first question, used as the answer name
+const QUESTION_NAME_POINTER: u16 = 0xC000 | HEADER_SIZE as u16;
+
+/// Record type for IPv4 addresses
+const TYPE_A: u16 = 1;
+/// Record type for IPv6 addresses
+const TYPE_AAAA: u16 = 28;
+/// Query type for all records
+const TYPE_ANY: u16 = 255;
+/// Internet record class
+const CLASS_IN: u16 = 1;
+
+/// Flag for messages that are responses
+const FLAG_RESPONSE: u16 = 0x8000;
+/// Flag for authoritative answers
+const FLAG_AUTHORITATIVE: u16 = 0x0400;
+/// Flag for queries requesting recursion
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+/// Flag for recursion being available
+const FLAG_RECURSION_AVAILABLE: u16 = 0x0080;
+
+/// Binds the DNS server socket to the `address`, the socket is bound before
+/// root privileges are dropped as the default address uses a privileged port
+///
+/// ## Arguments
+/// * `address` - The address to listen on
+pub fn bind(address: SocketAddr) -> io::Result<StdUdpSocket> {
+    let socket = StdUdpSocket::bind(address)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+/// Starts the DNS server on a background thread using the bound `socket`,
+/// upstream server and redirects from the `settings`. A warning is shown if
+/// the socket couldn't be bound or the server couldn't be started
+///
+/// ## Arguments
+/// * `settings` - The resolved settings
+/// * `socket`   - The socket from [`bind`]
+pub fn start(settings: &Settings, socket: io::Result<StdUdpSocket>) {
+    let address = settings.dns_address;
+    let upstream = settings
+        .dns_upstream
+        .unwrap_or_else(|| system_upstream(address));
+
+    let socket = match socket {
+        Ok(value) => value,
+        Err(err) => {
+            error!("Failed to start DNS server on {}: {}", address, err);
+            show_warning(
+                "Failed to start DNS server",
+                &format!(
+                    "Failed to listen on {address}: {err}\n\nEnsure no other DNS server is \
+                    using the address, listening on ports below 1024 may require admin \
+                    permissions"
+                ),
+            );
+            return;
+        }
+    };
+
+    let redirects: Arc<[Redirect]> = settings.redirects.clone().into();
+
+    let result = std::thread::Builder::new()
+        .name("dns-server".to_string())
+        .spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Failed building tokio runtime");
+            runtime.block_on(serve(socket, redirects, upstream));
+        });
+
+    match result {
+        Ok(_) => debug!(
+            "Started DNS server on {} forwarding to {}",
+            address, upstream
+        ),
+        Err(err) => error!("Failed to start DNS server thread: {}", err),
+    }
+}
+
+/// Finds the upstream DNS server from the system resolver config, servers
+/// using the same address as the DNS server are skipped
+///
+/// ## Arguments
+/// * `address` - The address of the DNS server
+#[cfg(target_family = "unix")]
+fn system_upstream(address: SocketAddr) -> SocketAddr {
+    std::fs::read_to_string("/etc/resolv.conf")
+        .ok()
+        .and_then(|value| {
+            value
+                .lines()
+                .filter_map(|line| line.trim().strip_prefix("nameserver"))
+                .filter_map(|value| value.trim().parse::<IpAddr>().ok())
+                .find(|value| *value != address.ip())
+        })
+        .map(|value| SocketAddr::new(value, 53))
+        .unwrap_or(FALLBACK_UPSTREAM)
+}
+
+/// Windows doesn't have a resolver config file so the fallback
+/// upstream server is used
+#[cfg(target_family = "windows")]
+fn system_upstream(_address: SocketAddr) -> SocketAddr {
+    FALLBACK_UPSTREAM
+}
+
+/// Serves DNS queries received on the provided `socket`
+///
+/// ## Arguments
+/// * `socket`    - The bound socket to serve on
+/// * `redirects` - The redirects to answer
+/// * `upstream`  - The upstream server to forward other queries to
+async fn serve(socket: StdUdpSocket, redirects: Arc<[Redirect]>, upstream: SocketAddr) {
+    let socket = match UdpSocket::from_std(socket) {
+        Ok(value) => Arc::new(value),
+        Err(err) => {
+            error!("Failed to start DNS server: {}", err);
+            return;
+        }
+    };
+
+    let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+
+    loop {
+        let (length, client) = match socket.recv_from(&mut buffer).await {
+            Ok(value) => value,
+            // Windows reports unreachable clients from earlier responses as errors
+            Err(err) => {
+                debug!("Failed to receive DNS query: {}", err);
+                continue;
+            }
+        };
+
+        let query = &buffer[..length];
+
+        if let Some(response) = answer_query(query, &redirects) {
+            if let Err(err) = socket.send_to(&response, client).await {
+                debug!("Failed to send DNS response: {}", err);
+            }
+            continue;
+        }
+
+        let query = query.to_vec();
+        let socket = socket.clone();
+
+        tokio::spawn(async move {
+            match forward_query(&query, upstream).await {
+                Ok(response) => {
+                    if let Err(err) = socket.send_to(&response, client).await {
+                        debug!("Failed to send DNS response: {}", err);
+                    }
+                }
+                Err(err) => debug!("Failed to forward DNS query: {}", err),
+            }
+        });
+    }
+}
+
+/// Sends the `query` to the `upstream` server and provides the response
+///
+/// ## Arguments
+/// * `query`    - The DNS query message
+/// * `upstream` - The server to send the query to
+async fn forward_query(query: &[u8], upstream: SocketAddr) -> io::Result<Vec<u8>> {
+    let bind_address: IpAddr = match upstream {
+        SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+
+    let socket = UdpSocket::bind((bind_address, 0)).await?;
+    socket.connect(upstream).await?;
+    socket.send(query).await?;
+
+    let mut buffer = vec![0u8; MAX_MESSAGE_SIZE];
+    let length = timeout(UPSTREAM_TIMEOUT, socket.recv(&mut buffer))
+        .await
+        .map_err(|_| io::Error::from(ErrorKind::TimedOut))??;
+    buffer.truncate(length);
+
+    Ok(buffer)
+}
+
+/// Resolves the addresses for the `host` using the DNS server at `server`,
+/// used to check that the DNS server is answering queries
+///
+/// ## Arguments
+/// * `server` - The address of the DNS server
+/// * `host`   - The host name to resolve
+/// * `ipv6`   - Whether to resolve IPv6 addresses rather than IPv4
+pub async fn resolve(server: SocketAddr, host: &str, ipv6: bool) -> io::Result<Vec<IpAddr>> {
+    let query_type = if ipv6 { TYPE_AAAA } else { TYPE_A };
+    let query = create_query(host, query_type);
+    let response = forward_query(&query, server).await?;
+
+    parse_addresses(&query, &response)
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "Invalid DNS response"))
+}
+
+/// Question section from a DNS message
+struct Question {
+    /// The queried host name
+    name: String,
+    /// The queried record type
+    query_type: u16,
+    /// The queried record class
+    query_class: u16,
+    /// Offset in the message after the end of the question
+    end: usize,
+}
+
+/// Reads a big endian u16 from the `message` at `offset`
+///
+/// ## Arguments
+/// * `message` - The message to read from
+/// * `offset`  - The offset to read at
+fn read_u16(message: &[u8], offset: usize) -> Option<u16> {
+    let bytes = message.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// Parses the first question from the DNS `message`
+///
+/// ## Arguments
+/// * `message` - The DNS message
+fn parse_question(message: &[u8]) -> Option<Question> {
+    let mut offset = HEADER_SIZE;
+    let mut labels = Vec::new();
+
+    loop {
+        let length = *message.get(offset)? as usize;
+        offset += 1;
+
+        if length == 0 {
+            break;
+        }
+
+        // Compressed names aren't used in the first question
+        if length & 0xC0 != 0 {
+            return None;
+        }
+
+        let label = message.get(offset..offset + length)?;
+        labels.push(String::from_utf8_lossy(label));
+        offset += length;
+    }
+
+    Some(Question {
+        name: labels.join("."),
+        query_type: read_u16(message, offset)?,
+        query_class: read_u16(message, offset + 2)?,
+        end: offset + 4,
+    })
+}
+
+/// Creates the response for a `query` for one of the `redirects` hosts,
+/// provides [`None`] for queries that should be forwarded upstream
+///
+/// ## Arguments
+/// * `query`     - The DNS query message
+/// * `redirects` - The redirects to answer
+fn answer_query(query: &[u8], redirects: &[Redirect]) -> Option<Vec<u8>> {
+    let flags = read_u16(query, 2)?;
+    let question_count = read_u16(query, 4)?;
+
+    // Only standard queries with a single question are answered
+    let opcode = (flags >> 11) & 0xF;
+    if flags & FLAG_RESPONSE != 0 || opcode != 0 || question_count != 1 {
+        return None;
+    }
+
+    let question = parse_question(query)?;
+    if question.query_class != CLASS_IN {
+        return None;
+    }
+
+    let redirect = redirects
+        .iter()
+        .find(|redirect| redirect.matches(&question.name))?;
+
+    // Other record types are answered without any records so they aren't resolved upstream
+    let answer: Option<(u16, Vec<u8>)> = match (question.query_type, redirect.address) {
+        (TYPE_A | TYPE_ANY, IpAddr::V4(address)) => Some((TYPE_A, address.octets().to_vec())),
+        (TYPE_AAAA | TYPE_ANY, IpAddr::V6(address)) => Some((TYPE_AAAA, address.octets().to_vec())),
+        _ => None,
+    };
+
+    let flags = FLAG_RESPONSE
+        | FLAG_AUTHORITATIVE
+        | (flags & FLAG_RECURSION_DESIRED)
+        | FLAG_RECURSION_AVAILABLE;
+
+    let mut response = Vec::with_capacity(question.end + 32);
+    // Message ID
+    response.extend_from_slice(&query[0..2]);
+    response.extend_from_slice(&flags.to_be_bytes());
+    // Question, answer, authority and additional counts
+    response.extend_from_slice(&1u16.to_be_bytes());
+    response.extend_from_slice(&u16::from(answer.is_some()).to_be_bytes());
+    response.extend_from_slice(&[0, 0, 0, 0]);
+    response.extend_from_slice(&query[HEADER_SIZE..question.end]);
+
+    if let Some((record_type, data)) = answer {
+        response.extend_from_slice(&QUESTION_NAME_POINTER.to_be_bytes());
+        response.extend_from_slice(&record_type.to_be_bytes());
+        response.extend_from_slice(&CLASS_IN.to_be_bytes());
+        response.extend_from_slice(&ANSWER_TTL.to_be_bytes());
+        response.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        response.extend_from_slice(&data);
+    }
+
+    Some(response)
+}
+
+/// Creates a DNS query message for the `host`
+///
+/// ## Arguments
+/// * `host`       - The host name to query
+/// * `query_type` - The record type to query
+fn create_query(host: &str, query_type: u16) -> Vec<u8> {
+    let mut query = Vec::with_capacity(HEADER_SIZE + host.len() + 6);
+    // Message ID
+    query.extend_from_slice(&0x5052u16.to_be_bytes());
+    query.extend_from_slice(&FLAG_RECURSION_DESIRED.to_be_bytes());
+    // Question, answer, authority and additional counts
+    query.extend_from_slice(&1u16.to_be_bytes());
+    query.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+
+    for label in host.split('.').filter(|label| !label.is_empty()) {
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+
+    query.push(0);
+    query.extend_from_slice(&query_type.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    query
+}
+
+/// Parses the addresses from the answers in the `response` to the `query`
+///
+/// ## Arguments
+/// * `query`    - The DNS query message
+/// * `response` - The DNS response message
+fn parse_addresses(query: &[u8], response: &[u8]) -> Option<Vec<IpAddr>> {
+    // Response must be for the query
+    if response.get(0..2)? != query.get(0..2)? {
+        return None;
+    }
+
+    let answer_count = read_u16(response, 6)?;
+    let mut offset = parse_question(response)?.end;
+    let mut addresses = Vec::new();
+
+    for _ in 0..answer_count {
+        // Skip the answer name
+        loop {
+            let length = *response.get(offset)?;
+            if length & 0xC0 == 0xC0 {
+                offset += 2;
+                break;
+            }
+
+            offset += 1 + length as usize;
+            if length == 0 {
+                break;
+            }
+        }
+
+        let record_type = read_u16(response, offset)?;
+        let data_length = read_u16(response, offset + 8)? as usize;
+        let data = response.get(offset + 10..offset + 10 + data_length)?;
+        offset += 10 + data_length;
+
+        match (record_type, data.len()) {
+            (TYPE_A, 4) => addresses.push(IpAddr::from(<[u8; 4]>::try_from(data).ok()?)),
+            (TYPE_AAAA, 16) => addresses.push(IpAddr::from(<[u8; 16]>::try_from(data).ok()?)),
+            _ => {}
+        }
+    }
+
+    Some(addresses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Redirects used by the tests
+    fn redirects() -> Vec<Redirect> {
+        Redirect::defaults()
+    }
+
+    #[test]
+    fn answers_redirected_host() {
+        let query = create_query("GOSREDIRECTOR.ea.com", TYPE_A);
+        let response = answer_query(&query, &redirects()).unwrap();
+
+        assert_eq!(
+            parse_addresses(&query, &response),
+            Some(vec![IpAddr::V4(Ipv4Addr::LOCALHOST)])
+        );
+    }
+
+    #[test]
+    fn answers_other_types_without_records() {
+        let query = create_query("gosredirector.ea.com", TYPE_AAAA);
+        let response = answer_query(&query, &redirects()).unwrap();
+
+        assert_eq!(parse_addresses(&query, &response), Some(Vec::new()));
+    }
+
+    #[test]
+    fn forwards_other_hosts() {
+        let query = create_query("example.com", TYPE_A);
+
+        assert!(answer_query(&query, &redirects()).is_none());
+    }
+
+    #[test]
+    fn ignores_invalid_queries() {
+        assert!(answer_query(&[], &redirects()).is_none());
+        assert!(answer_query(&[0; HEADER_SIZE], &redirects()).is_none());
+
+        // Truncated question
+        let query = create_query("gosredirector.ea.com", TYPE_A);
+        assert!(answer_query(&query[..query.len() - 3], &redirects()).is_none());
+    }
+}
@@ -0,0 +1,570 @@
+//! Diagnostics for troubleshooting why the game isn't connecting to the
+//! server, checks the redirect, local servers, connection and identity
+
+use crate::{
+    config::ClientConfig,
+    control::request_client_status,
+    core::{
+        api::{create_http_client, lookup_server, read_client_identity},
+        reqwest,
+    },
+    dns,
+    hosts::{
+        find_host_entries, has_running_client, is_conflicting_line, FileBackend, HostEntryGuard,
+        Redirect, HOSTS_PATH,
+    },
+    servers::{check_server_port, server_states, LocalServer, ServerState},
+    settings::Settings,
+};
+use serde_json::Value;
+use std::{
+    fmt::Display,
+    io::ErrorKind,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    path::PathBuf,
+    process::ExitCode,
+};
+
+/// Hint for fixing a missing hosts file redirect
+const HOSTS_HINT: &str = "Run the client as admin (or root) so it can modify the hosts file, \
+    or run `pocket-relay-client hosts apply` as admin";
+/// Hint for fixing the redirect not resolving to localhost
+const RESOLVE_HINT: &str = "Remove any other gosredirector.ea.com entries from the hosts file \
+    and flush the DNS cache (`ipconfig /flushdns` on Windows), VPNs and custom DNS \
+    software can also bypass the hosts file";
+/// Hint for fixing the DNS server not running
+const DNS_HINT: &str = "Ensure no other DNS server is using the address, listening on ports \
+    below 1024 requires admin permissions (or the CAP_NET_BIND_SERVICE capability on Linux)";
+/// Hint for fixing local server ports being in use
+const PORT_HINT: &str = "Close any other running Pocket Relay clients or servers, \
+    or other programs using the port";
+/// Hint for fixing an unreachable server
+const CONNECTION_HINT: &str = "Check the Connection URL is correct and that the server \
+    is running and reachable from this computer";
+/// Hint for fixing an invalid identity file
+const IDENTITY_HINT: &str = "Ensure the identity file is a valid PKCS#12 (.p12 / .pfx) file \
+    without a password";
+
+/// Outcome of a single diagnostic check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    /// Check passed
+    Pass,
+    /// Check found something that may cause issues
+    Warning,
+    /// Check failed
+    Fail,
+}
+
+impl Display for CheckStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CheckStatus::Pass => "PASS",
+            CheckStatus::Warning => "WARN",
+            CheckStatus::Fail => "FAIL",
+        })
+    }
+}
+
+/// Result of a single diagnostic check
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    /// Name of the check
+    pub name: String,
+    /// Outcome of the check
+    pub status: CheckStatus,
+    /// Details about the outcome
+    pub message: String,
+    /// Hint for fixing the problem when the check didn't pass
+    pub hint: Option<&'static str>,
+}
+
+impl CheckResult {
+    /// Creates a passing check result
+    ///
+    /// ## Arguments
+    /// * `name`    - The name of the check
+    /// * `message` - Details about the outcome
+    fn pass(name: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: CheckStatus::Pass,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    /// Creates a check result that didn't pass with a hint for fixing it
+    ///
+    /// ## Arguments
+    /// * `status`  - The outcome of the check
+    /// * `name`    - The name of the check
+    /// * `message` - Details about the outcome
+    /// * `hint`    - Hint for fixing the problem
+    fn problem(
+        status: CheckStatus,
+        name: impl Into<String>,
+        message: impl Into<String>,
+        hint: &'static str,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            status,
+            message: message.into(),
+            hint: Some(hint),
+        }
+    }
+}
+
+/// Report containing the results of all the diagnostic checks
+#[derive(Debug, Clone, Default)]
+pub struct DoctorReport {
+    /// The check results
+    pub checks: Vec<CheckResult>,
+}
+
+impl DoctorReport {
+    /// Checks whether any of the checks failed
+    pub fn has_failures(&self) -> bool {
+        self.checks
+            .iter()
+            .any(|check| check.status == CheckStatus::Fail)
+    }
+}
+
+impl Display for DoctorReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for check in &self.checks {
+            writeln!(f, "[{}] {}: {}", check.status, check.name, check.message)?;
+            if let Some(hint) = check.hint {
+                writeln!(f, "       Hint: {}", hint)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs all the diagnostic checks
+///
+/// ## Arguments
+/// * `http_client`       - The HTTP client to test the connection with
+/// * `connection_url`    - The connection URL to test if one is set
+/// * `identity_path`     - Path to the client identity file
+/// * `identity_explicit` - Whether the identity path was explicitly provided
+/// * `redirects`         - The redirects to check
+/// * `dns_server`        - The DNS server address when using the DNS redirect mode
+pub async fn run_diagnostics(
+    http_client: reqwest::Client,
+    connection_url: Option<String>,
+    identity_path: PathBuf,
+    identity_explicit: bool,
+    redirects: Vec<Redirect>,
+    dns_server: Option<SocketAddr>,
+) -> DoctorReport {
+    let mut checks = Vec::new();
+
+    match dns_server {
+        Some(address) => checks.extend(check_dns(address, &redirects).await),
+        None => {
+            checks.push(check_hosts_file(&redirects));
+            for redirect in &redirects {
+                checks.push(check_resolve(redirect).await);
+            }
+        }
+    }
+
+    checks.extend(check_ports());
+    checks.push(check_connection(http_client, connection_url).await);
+    checks.push(check_identity(identity_path, identity_explicit));
+
+    DoctorReport { checks }
+}
+
+/// Runs the diagnostics from the command line printing the report,
+/// provides a failure exit code if any of the checks failed
+///
+/// ## Arguments
+/// * `config`   - The client config if one was loaded
+/// * `settings` - The resolved settings
+pub fn run_doctor_command(config: Option<&ClientConfig>, settings: &Settings) -> ExitCode {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Failed building tokio runtime");
+
+    // Use the identity without asking, the identity check reports if its invalid
+    let identity = read_client_identity(&settings.identity_path).ok();
+    let http_client = create_http_client(identity).expect("Failed to create HTTP client");
+
+    let connection_url = settings.connection_url.clone().or_else(|| {
+        config
+            .and_then(ClientConfig::last_used_profile)
+            .map(|profile| profile.connection_url.clone())
+    });
+
+    let report = runtime.block_on(run_diagnostics(
+        http_client,
+        connection_url,
+        settings.identity_path.clone(),
+        settings.identity_explicit,
+        settings.redirects.clone(),
+        settings.dns_server(),
+    ));
+
+    print!("{report}");
+
+    if report.has_failures() {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Checks the hosts file contains the entries for the `redirects`
+///
+/// ## Arguments
+/// * `redirects` - The redirects to check
+fn check_hosts_file(redirects: &[Redirect]) -> CheckResult {
+    const NAME: &str = "Hosts file";
+
+    let entries = match find_host_entries(&FileBackend::system(), redirects) {
+        Ok(value) => value,
+        Err(err) => {
+            return CheckResult::problem(CheckStatus::Fail, NAME, err.to_string(), HOSTS_HINT)
+        }
+    };
+
+    let missing: Vec<&str> = redirects
+        .iter()
+        .filter(|redirect| {
+            let redirect = std::slice::from_ref(*redirect);
+            !entries
+                .iter()
+                .any(|line| HostEntryGuard::is_host_line(line, redirect))
+        })
+        .map(|redirect| redirect.host.as_str())
+        .collect();
+
+    let conflicts: Vec<&str> = entries
+        .iter()
+        .map(String::as_str)
+        .filter(|line| is_conflicting_line(line, redirects))
+        .collect();
+
+    if !missing.is_empty() {
+        CheckResult::problem(
+            CheckStatus::Fail,
+            NAME,
+            format!("{HOSTS_PATH} has no entry for {}", missing.join(", ")),
+            HOSTS_HINT,
+        )
+    } else if !conflicts.is_empty() {
+        CheckResult::problem(
+            CheckStatus::Fail,
+            NAME,
+            format!("Conflicting entries: {}", conflicts.join(", ")),
+            RESOLVE_HINT,
+        )
+    } else {
+        CheckResult::pass(NAME, entries.join(", "))
+    }
+}
+
+/// Checks that the `redirect` host resolves to the redirect address
+///
+/// ## Arguments
+/// * `redirect` - The redirect to check
+async fn check_resolve(redirect: &Redirect) -> CheckResult {
+    const NAME: &str = "Redirect resolution";
+
+    let host = redirect.host.as_str();
+    let addresses: Vec<IpAddr> = match tokio::net::lookup_host((host, 0)).await {
+        Ok(value) => value.map(|address| address.ip()).collect(),
+        Err(err) => {
+            return CheckResult::problem(
+                CheckStatus::Fail,
+                NAME,
+                format!("Failed to resolve {host}: {err}"),
+                RESOLVE_HINT,
+            )
+        }
+    };
+
+    let message = format!(
+        "{host} resolves to {}",
+        addresses
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    // Loopback redirects can resolve to any of the loopback addresses
+    let expected = |address: &IpAddr| {
+        *address == redirect.address || (redirect.address.is_loopback() && address.is_loopback())
+    };
+
+    if addresses.contains(&redirect.address) && addresses.iter().all(expected) {
+        CheckResult::pass(NAME, message)
+    } else {
+        CheckResult::problem(CheckStatus::Fail, NAME, message, RESOLVE_HINT)
+    }
+}
+
+/// Checks that the built-in DNS server at `address` answers the `redirects`
+/// hosts, when nothing answers at the address it is checked to be free
+///
+/// ## Arguments
+/// * `address`   - The address of the DNS server
+/// * `redirects` - The redirects to check
+async fn check_dns(address: SocketAddr, redirects: &[Redirect]) -> Vec<CheckResult> {
+    const NAME: &str = "DNS server";
+
+    // Servers listening on all addresses are queried using loopback
+    let server = match address.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => {
+            SocketAddr::new(Ipv4Addr::LOCALHOST.into(), address.port())
+        }
+        IpAddr::V6(ip) if ip.is_unspecified() => {
+            SocketAddr::new(Ipv6Addr::LOCALHOST.into(), address.port())
+        }
+        _ => address,
+    };
+
+    let mut checks = Vec::with_capacity(redirects.len());
+
+    for redirect in redirects {
+        let host = redirect.host.as_str();
+        let result = match dns::resolve(server, host, redirect.address.is_ipv6()).await {
+            Ok(addresses) => {
+                let message = format!(
+                    "{host} resolves to {} using {address}",
+                    addresses
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+
+                if addresses.contains(&redirect.address) {
+                    CheckResult::pass(NAME, message)
+                } else {
+                    CheckResult::problem(CheckStatus::Fail, NAME, message, DNS_HINT)
+                }
+            }
+            // The server isn't running when nothing answers the first query
+            Err(_) if checks.is_empty() => {
+                let result = match UdpSocket::bind(address) {
+                    Ok(_) => CheckResult::pass(NAME, format!("{address} is free")),
+                    Err(err) => CheckResult::problem(
+                        CheckStatus::Fail,
+                        NAME,
+                        format!("Unable to listen on {address}: {err}"),
+                        DNS_HINT,
+                    ),
+                };
+                return vec![result];
+            }
+            Err(err) => CheckResult::problem(
+                CheckStatus::Fail,
+                NAME,
+                format!("No answer for {host} from {address}: {err}"),
+                DNS_HINT,
+            ),
+        };
+
+        checks.push(result);
+    }
+
+    checks
+}
+
+/// Checks whether the port for each local server is free or held by
+/// the client, ports held by a server of another running client (i.e.
+/// when running the `doctor` command while the client is open) aren't
+/// problems. Ports the client couldn't start its server on are problems
+/// as another program is using them
+fn check_ports() -> Vec<CheckResult> {
+    let mut other_client: Option<OtherClient> = None;
+
+    server_states()
+        .into_iter()
+        .map(|(server, state)| {
+            let name = format!("{} port", server.name);
+            let err = match check_server_port(server) {
+                Ok(_) => {
+                    return CheckResult::pass(
+                        name,
+                        format!("{} {} is free", server.protocol, server.port),
+                    )
+                }
+                Err(err) => err,
+            };
+
+            if err.kind() == ErrorKind::AddrInUse {
+                if state == ServerState::Running {
+                    return CheckResult::pass(
+                        name,
+                        format!(
+                            "{} {} is in use by the client",
+                            server.protocol, server.port
+                        ),
+                    );
+                }
+
+                // Only checked once as the process check can be slow
+                let other_client = other_client.get_or_insert_with(OtherClient::find);
+
+                match other_client.is_serving(server) {
+                    Some(true) => {
+                        return CheckResult::pass(
+                            name,
+                            format!(
+                                "{} {} is in use by another running client",
+                                server.protocol, server.port
+                            ),
+                        )
+                    }
+                    // The servers of clients without the control API are unknown
+                    None => {
+                        return CheckResult::problem(
+                            CheckStatus::Warning,
+                            name,
+                            format!(
+                                "{} {} is in use, another client is running which may be using it",
+                                server.protocol, server.port
+                            ),
+                            PORT_HINT,
+                        )
+                    }
+                    Some(false) => {}
+                }
+            }
+
+            CheckResult::problem(
+                CheckStatus::Fail,
+                name,
+                format!(
+                    "{} {} is unavailable: {}",
+                    server.protocol, server.port, err
+                ),
+                PORT_HINT,
+            )
+        })
+        .collect()
+}
+
+/// Another running client found by the port check
+enum OtherClient {
+    /// No other client is running
+    None,
+    /// Client with the control API enabled, contains its status response
+    Status(Value),
+    /// Client without the control API, its servers are unknown
+    Unknown,
+}
+
+impl OtherClient {
+    /// Finds another running client using its control API or the entries
+    /// it added to the hosts file
+    fn find() -> Self {
+        if let Some(status) = request_client_status() {
+            return Self::Status(status);
+        }
+
+        if has_running_client(&FileBackend::system()) {
+            Self::Unknown
+        } else {
+            Self::None
+        }
+    }
+
+    /// Checks whether the client is running the local `server`, provides
+    /// [`None`] when it isn't known
+    ///
+    /// ## Arguments
+    /// * `server` - The local server
+    fn is_serving(&self, server: &LocalServer) -> Option<bool> {
+        match self {
+            Self::None => Some(false),
+            Self::Unknown => None,
+            Self::Status(status) => Some(
+                status
+                    .get("servers")
+                    .and_then(Value::as_array)
+                    .is_some_and(|servers| {
+                        servers.iter().any(|value| {
+                            value.get("name").and_then(Value::as_str) == Some(server.name)
+                                && value.get("state").and_then(Value::as_str) == Some("running")
+                        })
+                    }),
+            ),
+        }
+    }
+}
+
+/// Checks that the server at the `connection_url` is reachable
+///
+/// ## Arguments
+/// * `http_client`    - The HTTP client to connect with
+/// * `connection_url` - The connection URL to test if one is set
+async fn check_connection(
+    http_client: reqwest::Client,
+    connection_url: Option<String>,
+) -> CheckResult {
+    const NAME: &str = "Server connection";
+
+    let Some(connection_url) = connection_url.filter(|value| !value.trim().is_empty()) else {
+        return CheckResult::problem(
+            CheckStatus::Warning,
+            NAME,
+            "No connection URL is set",
+            CONNECTION_HINT,
+        );
+    };
+
+    match lookup_server(http_client, connection_url.clone()).await {
+        Ok(value) => CheckResult::pass(
+            NAME,
+            format!("{} is reachable (version v{})", value.url, value.version),
+        ),
+        Err(err) => CheckResult::problem(
+            CheckStatus::Fail,
+            NAME,
+            format!("{connection_url}: {err}"),
+            CONNECTION_HINT,
+        ),
+    }
+}
+
+/// Checks that the client identity file is valid if one is present
+///
+/// ## Arguments
+/// * `identity_path`     - Path to the client identity file
+/// * `identity_explicit` - Whether the identity path was explicitly provided
+fn check_identity(identity_path: PathBuf, identity_explicit: bool) -> CheckResult {
+    const NAME: &str = "Client identity";
+
+    if !identity_path.exists() {
+        if identity_explicit {
+            return CheckResult::problem(
+                CheckStatus::Fail,
+                NAME,
+                format!("{} is missing", identity_path.display()),
+                IDENTITY_HINT,
+            );
+        }
+
+        return CheckResult::pass(NAME, "No identity file (Only required by some servers)");
+    }
+
+    match read_client_identity(&identity_path) {
+        Ok(_) => CheckResult::pass(NAME, format!("{} is valid", identity_path.display())),
+        Err(err) => CheckResult::problem(
+            CheckStatus::Fail,
+            NAME,
+            format!("{}: {}", identity_path.display(), err),
+            IDENTITY_HINT,
+        ),
+    }
+}
@@ -0,0 +1,114 @@
+//! Helpers for safely writing files
+
+use std::{
+    ffi::OsStr,
+    fs::{remove_file, rename, File, Permissions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Suffix appended to the file name of the temporary file used while
+/// writing files atomically
+const TMP_SUFFIX: &str = ".tmp";
+
+/// Writes the provided `bytes` to the file at `path` atomically. The bytes
+/// are written and synced to a temporary file next to `path` which is then
+/// renamed over `path` so the file is never left partially written
+///
+/// ## Arguments
+/// * `path`  - The path of the file to write
+/// * `bytes` - The bytes to write to the file
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    write_atomic_inner(path, bytes, None)
+}
+
+/// Writes the provided `bytes` to the file at `path` atomically like
+/// [`write_atomic`], the `permissions` are applied to the temporary file
+/// before it replaces `path`
+///
+/// ## Arguments
+/// * `path`        - The path of the file to write
+/// * `bytes`       - The bytes to write to the file
+/// * `permissions` - The permissions for the file
+pub fn write_atomic_with_permissions(
+    path: &Path,
+    bytes: &[u8],
+    permissions: Permissions,
+) -> io::Result<()> {
+    write_atomic_inner(path, bytes, Some(permissions))
+}
+
+/// Writes the `bytes` atomically to `path` applying the optional `permissions`
+///
+/// ## Arguments
+/// * `path`        - The path of the file to write
+/// * `bytes`       - The bytes to write to the file
+/// * `permissions` - The permissions for the file
+fn write_atomic_inner(
+    path: &Path,
+    bytes: &[u8],
+    permissions: Option<Permissions>,
+) -> io::Result<()> {
+    let tmp_path = with_suffix(path, TMP_SUFFIX);
+
+    let result = write_synced(&tmp_path, bytes, permissions).and_then(|_| rename(&tmp_path, path));
+
+    if let Err(err) = result {
+        // Don't leave the partially written temporary file behind
+        let _ = remove_file(&tmp_path);
+        return Err(err);
+    }
+
+    sync_parent_dir(path)
+}
+
+/// Writes the `bytes` to a new file at `path` and syncs the file
+/// contents to the disk
+///
+/// ## Arguments
+/// * `path`        - The path of the file to write
+/// * `bytes`       - The bytes to write to the file
+/// * `permissions` - The optional permissions for the file
+fn write_synced(path: &Path, bytes: &[u8], permissions: Option<Permissions>) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(bytes)?;
+    if let Some(permissions) = permissions {
+        file.set_permissions(permissions)?;
+    }
+    file.sync_all()
+}
+
+/// Syncs the parent directory of `path` to ensure a rename within
+/// the directory is persisted
+///
+/// ## Arguments
+/// * `path` - The path within the directory to sync
+#[cfg(target_family = "unix")]
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    match path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        Some(parent) => File::open(parent)?.sync_all(),
+        None => Ok(()),
+    }
+}
+
+/// Directories cannot be opened for syncing on Windows, renames are
+/// persisted by the file system journal instead
+#[cfg(target_family = "windows")]
+fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Creates a new path with the `suffix` appended to the file name
+/// of the provided `path`
+///
+/// ## Arguments
+/// * `path`   - The path to append to
+/// * `suffix` - The suffix to append
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or(OsStr::new("")).to_os_string();
+    file_name.push(suffix);
+    path.with_file_name(file_name)
+}
@@ -0,0 +1,183 @@
+//! Bounded history of recent connection attempts and their outcomes,
+//! stored in its own file next to the config file
+
+use crate::{
+    config::{config_path, normalize_url, unix_timestamp},
+    core::api::{LookupData, LookupError},
+    files::write_atomic,
+};
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, path::PathBuf};
+
+/// Name of the history file
+pub const HISTORY_FILE_NAME: &str = "pocket-relay-history.json";
+/// Maximum number of entries kept in the history
+const MAX_HISTORY_ENTRIES: usize = 20;
+/// Maximum length of error messages shown in the history list
+const MAX_ERROR_LENGTH: usize = 40;
+
+/// History of recent connection attempts, most recent first
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ConnectionHistory {
+    /// The history entries
+    pub entries: Vec<HistoryEntry>,
+}
+
+/// Single connection attempt in the history
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    /// The connection URL that was used
+    pub url: String,
+    /// Unix timestamp in seconds of when the attempt was made
+    pub timestamp: u64,
+    /// Outcome of the attempt
+    pub outcome: HistoryOutcome,
+}
+
+/// Outcome of a connection attempt
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HistoryOutcome {
+    /// Connected to a server of the provided version
+    Success { version: String },
+    /// Failed to connect with the provided error
+    Failed { error: String },
+}
+
+impl ConnectionHistory {
+    /// Adds the `entry` as the most recent entry replacing any previous
+    /// entries for the same URL
+    ///
+    /// ## Arguments
+    /// * `entry` - The entry to add
+    pub fn push(&mut self, entry: HistoryEntry) {
+        if entry.url.is_empty() {
+            return;
+        }
+
+        self.entries
+            .retain(|value| normalize_url(&value.url) != normalize_url(&entry.url));
+        self.entries.insert(0, entry);
+        self.entries.truncate(MAX_HISTORY_ENTRIES);
+    }
+
+    /// Removes all the history entries
+    #[cfg(any(feature = "iced", feature = "native"))]
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl HistoryEntry {
+    /// Creates a new entry for the `result` of a lookup for the
+    /// connection `url`
+    ///
+    /// ## Arguments
+    /// * `url`    - The connection URL that was looked up
+    /// * `result` - The lookup result
+    pub fn new(url: &str, result: &Result<LookupData, LookupError>) -> Self {
+        let outcome = match result {
+            Ok(value) => HistoryOutcome::Success {
+                version: value.version.to_string(),
+            },
+            Err(err) => HistoryOutcome::Failed {
+                error: err.to_string(),
+            },
+        };
+
+        Self {
+            url: url.trim().to_string(),
+            timestamp: unix_timestamp(),
+            outcome,
+        }
+    }
+}
+
+impl Display for HistoryEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let age = format_age(unix_timestamp().saturating_sub(self.timestamp));
+
+        match &self.outcome {
+            HistoryOutcome::Success { version } => {
+                write!(f, "✔ {} ({}, v{})", self.url, age, version)
+            }
+            HistoryOutcome::Failed { error } => {
+                let mut error = error.clone();
+                if let Some((index, _)) = error.char_indices().nth(MAX_ERROR_LENGTH) {
+                    error.truncate(index);
+                    error.push('…');
+                }
+
+                write!(f, "✖ {} ({}, {})", self.url, age, error)
+            }
+        }
+    }
+}
+
+/// Formats an age in `seconds` as a short human readable string
+///
+/// ## Arguments
+/// * `seconds` - The age in seconds
+fn format_age(seconds: u64) -> String {
+    match seconds {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        3600..=86399 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+/// Provides the path to the history file, stored next to the config file
+fn history_path() -> PathBuf {
+    config_path().with_file_name(HISTORY_FILE_NAME)
+}
+
+/// Reads the connection history file, an empty history is used if the
+/// file doesn't exist or is invalid
+pub fn read_history_file() -> ConnectionHistory {
+    let file_path = history_path();
+    if !file_path.exists() {
+        return ConnectionHistory::default();
+    }
+
+    let result = std::fs::read(&file_path)
+        .map_err(|err| err.to_string())
+        .and_then(|bytes| serde_json::from_slice(&bytes).map_err(|err| err.to_string()));
+
+    match result {
+        Ok(value) => value,
+        Err(err) => {
+            warn!("Failed to read connection history: {}", err);
+            ConnectionHistory::default()
+        }
+    }
+}
+
+/// Saves the connection `history` to the history file
+///
+/// ## Arguments
+/// * `history` - The history to save
+pub fn write_history_file(history: &ConnectionHistory) {
+    let file_path = history_path();
+    debug!("Writing connection history");
+
+    let bytes = match serde_json::to_vec(history) {
+        Ok(value) => value,
+        Err(err) => {
+            error!("Failed to serialize connection history: {}", err);
+            return;
+        }
+    };
+
+    if let Some(parent) = file_path.parent() {
+        if let Err(err) = std::fs::create_dir_all(parent) {
+            error!("Failed to create connection history directory: {}", err);
+            return;
+        }
+    }
+
+    if let Err(err) = write_atomic(&file_path, &bytes) {
+        error!("Failed to save connection history: {}", err);
+    }
+}
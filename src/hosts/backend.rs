@@ -0,0 +1,143 @@
+//! Backends providing access to the hosts file contents
+
+use super::{has_client_changes, HostsError, HOSTS_PATH};
+use crate::files::{with_suffix, write_atomic_with_permissions};
+use log::debug;
+use std::{
+    fs::{canonicalize, metadata, read, write, OpenOptions},
+    path::PathBuf,
+};
+
+/// Suffix appended to the hosts file name for the backup of the hosts
+/// file from before it was last modified
+const BACKUP_SUFFIX: &str = ".pocket-relay.bak";
+
+/// Backend for reading and writing the hosts file
+pub trait HostsBackend {
+    /// Reads the contents of the hosts file
+    fn read(&self) -> Result<Vec<u8>, HostsError>;
+
+    /// Writes the modified hosts file `output`, backends should keep a backup
+    /// of the `original` hosts file contents when they don't contain changes
+    /// made by the client so the backup isn't replaced by the client's changes
+    ///
+    /// ## Arguments
+    /// * `original` - The contents of the hosts file before modification
+    /// * `output`   - The modified contents to write
+    fn write(&self, original: &[u8], output: &[u8]) -> Result<(), HostsError>;
+}
+
+/// Backend for a hosts file on the file system
+pub struct FileBackend {
+    /// Path to the hosts file
+    path: PathBuf,
+}
+
+impl FileBackend {
+    /// Creates a backend for the hosts file at `path`
+    ///
+    /// ## Arguments
+    /// * `path` - The path to the hosts file
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Creates a backend for the system hosts file
+    pub fn system() -> Self {
+        Self::new(HOSTS_PATH)
+    }
+
+    /// Checks whether the hosts file can be written by the current process
+    pub fn is_writable(&self) -> bool {
+        OpenOptions::new().append(true).open(&self.path).is_ok()
+    }
+}
+
+impl HostsBackend for FileBackend {
+    fn read(&self) -> Result<Vec<u8>, HostsError> {
+        if !self.path.exists() {
+            return Err(HostsError::FileMissing);
+        }
+
+        // Read the hosts file
+        let bytes = read(&self.path)?;
+        Ok(bytes)
+    }
+
+    /// Writes the `output` atomically, a backup of the `original` contents
+    /// is written next to the hosts file first unless the client already
+    /// changed them
+    fn write(&self, original: &[u8], output: &[u8]) -> Result<(), HostsError> {
+        // Resolve links so that the linked file is replaced rather than the link
+        let path = canonicalize(&self.path)?;
+        let permissions = metadata(&path)?.permissions();
+
+        if !has_client_changes(original) {
+            let backup_path = with_suffix(&path, BACKUP_SUFFIX);
+            write(&backup_path, original)?;
+        }
+
+        // Keep the permissions of the replaced file
+        if let Err(err) = write_atomic_with_permissions(&path, output, permissions) {
+            // Files that can't be replaced (i.e. bind mounted in containers or
+            // when only the file is writable) are written directly
+            debug!("Failed to replace hosts file, writing directly: {}", err);
+            write(&path, output)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Backend for a hosts file held in memory
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryBackend {
+    /// Contents of the hosts file, [`None`] when the file is missing
+    pub contents: std::cell::RefCell<Option<Vec<u8>>>,
+    /// Backup of the contents from before the client changed them
+    pub backup: std::cell::RefCell<Option<Vec<u8>>>,
+    /// Whether writing is denied to simulate missing permissions
+    pub read_only: bool,
+}
+
+#[cfg(test)]
+impl MemoryBackend {
+    /// Creates a backend with the provided hosts file `contents`
+    ///
+    /// ## Arguments
+    /// * `contents` - The hosts file contents
+    pub fn new(contents: &[u8]) -> Self {
+        Self {
+            contents: std::cell::RefCell::new(Some(contents.to_vec())),
+            ..Default::default()
+        }
+    }
+
+    /// Provides a copy of the current hosts file contents
+    pub fn contents(&self) -> Vec<u8> {
+        self.contents.borrow().clone().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+impl HostsBackend for MemoryBackend {
+    fn read(&self) -> Result<Vec<u8>, HostsError> {
+        self.contents
+            .borrow()
+            .clone()
+            .ok_or(HostsError::FileMissing)
+    }
+
+    fn write(&self, original: &[u8], output: &[u8]) -> Result<(), HostsError> {
+        if self.read_only {
+            return Err(std::io::Error::from(std::io::ErrorKind::PermissionDenied).into());
+        }
+
+        if !has_client_changes(original) {
+            *self.backup.borrow_mut() = Some(original.to_vec());
+        }
+        *self.contents.borrow_mut() = Some(output.to_vec());
+        Ok(())
+    }
+}
@@ -0,0 +1,807 @@
+//! Privileged helper that modifies the hosts file on behalf of clients
+//! that aren't running as admin. The helper only manages the redirects
+//! from its own settings, clients send their redirects which are rejected
+//! when they don't match. Clients can only ask for the entries to be
+//! applied, removed or for their status. Uses a Unix socket on Unix and
+//! a named pipe on Windows, requests and responses are single lines of
+//! JSON
+//!
+//! Entries applied for a client are removed once every client that
+//! applied them has sent the remove command or disconnected
+
+use super::{
+    cleanup, find_host_entries, FileBackend, HostEntryGuard, HostsError, Redirect,
+    CONFLICTS_DISABLED, ENTRY_ADDED,
+};
+use crate::shutdown;
+#[cfg(target_family = "windows")]
+use log::warn;
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    process::ExitCode,
+    sync::{atomic::Ordering, Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt},
+    sync::Notify,
+};
+
+/// Path to the helper socket, only root can create files in this
+/// directory so other users can't impersonate the helper
+#[cfg(target_family = "unix")]
+pub const HELPER_SOCKET_PATH: &str = "/var/run/pocket-relay-hosts.sock";
+/// Name of the helper named pipe
+#[cfg(target_family = "windows")]
+pub const HELPER_PIPE_NAME: &str = r"\\.\pipe\pocket-relay-hosts";
+
+/// Access control for the helper named pipe, SYSTEM and administrators
+/// have full access and other users can only read and write so they
+/// can't create their own instances of the pipe
+#[cfg(target_family = "windows")]
+const HELPER_PIPE_SDDL: &str = "D:P(A;;GA;;;SY)(A;;GA;;;BA)(A;;GRGW;;;AU)";
+
+/// Time to wait for a response from the helper
+#[cfg(target_family = "unix")]
+const HELPER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+/// Time to wait for a helper started with admin permissions to start
+/// accepting connections, includes the time spent on the UAC prompt
+#[cfg(target_family = "windows")]
+const HELPER_START_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// Requests that can be sent to the helper
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum HelperRequest {
+    /// Apply the `redirects` entries, conflicting entries are commented
+    /// out when `disable_conflicts` is set
+    Apply {
+        redirects: Vec<Redirect>,
+        #[serde(default)]
+        disable_conflicts: bool,
+    },
+    /// Remove the redirect entries applied for this client
+    Remove,
+    /// Get the redirect entries in the hosts file
+    Status,
+}
+
+/// State shared between the helper connections
+struct HelperState {
+    /// The redirects managed by the helper
+    redirects: Vec<Redirect>,
+    /// Guard for the applied entries, removes them when dropped
+    guard: Option<HostEntryGuard>,
+    /// Number of connected clients that applied the entries
+    clients: usize,
+    /// Number of open connections
+    connections: usize,
+}
+
+impl HelperState {
+    /// Creates the helper state for the `redirects` managed by the helper
+    ///
+    /// ## Arguments
+    /// * `redirects` - The managed redirects
+    fn new(redirects: Vec<Redirect>) -> Self {
+        Self {
+            redirects,
+            guard: None,
+            clients: 0,
+            connections: 0,
+        }
+    }
+
+    /// Checks that the `redirects` from a client are the redirects managed
+    /// by the helper, provides the managed redirects
+    ///
+    /// ## Arguments
+    /// * `redirects` - The redirects from the client
+    fn check_redirects(&self, redirects: &[Redirect]) -> Result<Vec<Redirect>, HostsError> {
+        if self.redirects.is_empty() || !same_redirects(&self.redirects, redirects) {
+            return Err(HostsError::RedirectMismatch(
+                describe_redirects(&self.redirects),
+                describe_redirects(redirects),
+            ));
+        }
+
+        Ok(self.redirects.clone())
+    }
+
+    /// Applies the entries for a client, provides whether the entries
+    /// already existed
+    ///
+    /// ## Arguments
+    /// * `redirects`         - The redirects from the client
+    /// * `disable_conflicts` - Whether to comment out conflicting entries
+    fn apply(
+        &mut self,
+        redirects: &[Redirect],
+        disable_conflicts: bool,
+    ) -> Result<bool, HostsError> {
+        let redirects = self.check_redirects(redirects)?;
+        let backend = FileBackend::system();
+
+        // Conflicts left commented out by a client that crashed are restored
+        if self.clients == 0 {
+            HostEntryGuard::restore_orphans(&backend);
+        }
+
+        if disable_conflicts && !CONFLICTS_DISABLED.load(Ordering::SeqCst) {
+            HostEntryGuard::disable_conflicts(&backend, &redirects)?;
+            CONFLICTS_DISABLED.store(true, Ordering::SeqCst);
+        }
+
+        let existing = match &self.guard {
+            Some(guard) => guard.existing,
+            None => {
+                let guard = match HostEntryGuard::apply_entry(&backend, &redirects) {
+                    Ok(value) => value,
+                    Err(err) => {
+                        // Restore conflicts disabled for this request
+                        if self.clients == 0 {
+                            cleanup();
+                        }
+                        return Err(err);
+                    }
+                };
+                let existing = guard.existing;
+                self.guard = Some(guard);
+                existing
+            }
+        };
+
+        self.clients += 1;
+        Ok(existing)
+    }
+
+    /// Applies the entries again for a client that already applied them,
+    /// used when the entries were removed by another program. Provides
+    /// whether the entries were still present
+    ///
+    /// ## Arguments
+    /// * `redirects` - The redirects from the client
+    fn reapply(&self, redirects: &[Redirect]) -> Result<bool, HostsError> {
+        let redirects = self.check_redirects(redirects)?;
+        let existing = HostEntryGuard::add_entry(&FileBackend::system(), &redirects, true)?;
+        if !existing {
+            ENTRY_ADDED.store(true, Ordering::SeqCst);
+        }
+
+        Ok(existing)
+    }
+
+    /// Releases the entries for a client, the entries are removed
+    /// once no clients are using them
+    fn release(&mut self) {
+        self.clients = self.clients.saturating_sub(1);
+
+        if self.clients == 0 {
+            // Dropping the guard removes the entries and restores conflicts
+            self.guard = None;
+            debug!("Removed host entries, no clients are using them");
+        }
+    }
+}
+
+/// Runs the hosts helper until it is stopped, provides the exit code
+///
+/// ## Arguments
+/// * `redirects`      - The redirects to manage
+/// * `exit_when_idle` - Whether to stop once the last client disconnects
+pub fn run_hosts_helper(redirects: &[Redirect], exit_when_idle: bool) -> ExitCode {
+    if redirects.is_empty() {
+        eprintln!("No redirects configured for the hosts helper");
+        return ExitCode::FAILURE;
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed building tokio runtime");
+
+    // Remove the entries when the helper is stopped
+    shutdown::install_handlers();
+
+    let state = Arc::new(Mutex::new(HelperState::new(redirects.to_vec())));
+    let idle = exit_when_idle.then(|| Arc::new(Notify::new()));
+
+    match runtime.block_on(serve(state, idle)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Failed to start hosts helper: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs the hosts helper for the client that forked this process before
+/// dropping its root privileges, the helper only serves the client on the
+/// other end of the `stream` and exits once the client closes it. The
+/// `redirects` are resolved before the client drops its privileges so the
+/// unprivileged client can't choose the entries written as root
+///
+/// ## Arguments
+/// * `stream`    - The stream connected to the client
+/// * `redirects` - The redirects to manage
+#[cfg(target_family = "unix")]
+pub fn run_private_helper(stream: std::os::unix::net::UnixStream, redirects: Vec<Redirect>) -> ! {
+    // Signals sent to the process group are handled by the client which
+    // closes the stream once it has removed the entries
+    for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+        unsafe { libc::signal(signal, libc::SIG_IGN) };
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed building tokio runtime");
+
+    let state = Arc::new(Mutex::new(HelperState::new(redirects)));
+
+    runtime.block_on(async move {
+        let stream = stream
+            .set_nonblocking(true)
+            .and_then(|_| tokio::net::UnixStream::from_std(stream));
+
+        match stream {
+            Ok(stream) => handle_connection(stream, state).await,
+            Err(err) => error!("Failed to start private hosts helper: {}", err),
+        }
+    });
+
+    // Restore anything that wasn't removed when the entries were released
+    cleanup();
+    std::process::exit(0)
+}
+
+/// Accepts helper connections until the process is stopped, or until
+/// the last client disconnects when `idle` is provided
+///
+/// ## Arguments
+/// * `state` - The shared helper state
+/// * `idle`  - Notified when the last client disconnects
+#[cfg(target_family = "unix")]
+async fn serve(state: Arc<Mutex<HelperState>>, idle: Option<Arc<Notify>>) -> io::Result<()> {
+    let listener = bind().await?;
+
+    loop {
+        tokio::select! {
+            result = listener.accept() => match result {
+                Ok((stream, _)) => {
+                    accept_connection(stream, &state, &idle);
+                }
+                Err(err) => error!("Failed to accept hosts helper connection: {}", err),
+            },
+            _ = wait_idle(&idle) => {
+                if is_idle(&state) {
+                    info!("Hosts helper stopping, no clients are connected");
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Accepts helper connections until the process is stopped, or until
+/// the last client disconnects when `idle` is provided. A new instance
+/// of the named pipe is created for each connection
+///
+/// ## Arguments
+/// * `state` - The shared helper state
+/// * `idle`  - Notified when the last client disconnects
+#[cfg(target_family = "windows")]
+async fn serve(state: Arc<Mutex<HelperState>>, idle: Option<Arc<Notify>>) -> io::Result<()> {
+    let security = crate::pipe::PipeSecurity::new(HELPER_PIPE_SDDL)?;
+
+    // Fails if another process already created the pipe
+    let mut server = security.create_pipe(HELPER_PIPE_NAME, true)?;
+    info!("Hosts helper listening on {}", HELPER_PIPE_NAME);
+
+    loop {
+        tokio::select! {
+            result = server.connect() => match result {
+                Ok(()) => {
+                    let stream = std::mem::replace(&mut server, security.create_pipe(HELPER_PIPE_NAME, false)?);
+                    accept_connection(stream, &state, &idle);
+                }
+                Err(err) => {
+                    error!("Failed to accept hosts helper connection: {}", err);
+                    server = security.create_pipe(HELPER_PIPE_NAME, false)?;
+                }
+            },
+            _ = wait_idle(&idle) => {
+                if is_idle(&state) {
+                    info!("Hosts helper stopping, no clients are connected");
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Spawns a task handling the connection `stream`, `idle` is notified
+/// once the stream closes and no other connections are open
+///
+/// ## Arguments
+/// * `stream` - The connection stream
+/// * `state`  - The shared helper state
+/// * `idle`   - Notified when the last client disconnects
+fn accept_connection<S>(stream: S, state: &Arc<Mutex<HelperState>>, idle: &Option<Arc<Notify>>)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    if let Ok(mut state) = state.lock() {
+        state.connections += 1;
+    }
+
+    let state = state.clone();
+    let idle = idle.clone();
+
+    tokio::spawn(async move {
+        handle_connection(stream, state.clone()).await;
+
+        let connections = match state.lock() {
+            Ok(mut state) => {
+                state.connections = state.connections.saturating_sub(1);
+                state.connections
+            }
+            Err(_) => return,
+        };
+
+        if let (0, Some(idle)) = (connections, idle) {
+            idle.notify_one();
+        }
+    });
+}
+
+/// Waits for the `idle` notification, waits forever when the helper
+/// doesn't stop once idle
+///
+/// ## Arguments
+/// * `idle` - Notified when the last client disconnects
+async fn wait_idle(idle: &Option<Arc<Notify>>) {
+    match idle {
+        Some(idle) => idle.notified().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Checks whether no connections are open, connections may have been
+/// accepted after the idle notification was sent
+///
+/// ## Arguments
+/// * `state` - The shared helper state
+fn is_idle(state: &Mutex<HelperState>) -> bool {
+    state.lock().map_or(true, |state| state.connections == 0)
+}
+
+/// Binds the helper Unix socket, stale sockets from helpers that didn't
+/// exit cleanly are replaced
+#[cfg(target_family = "unix")]
+async fn bind() -> io::Result<tokio::net::UnixListener> {
+    use std::{fs::Permissions, os::unix::fs::PermissionsExt, path::Path};
+    use tokio::net::{UnixListener, UnixStream};
+
+    let path = Path::new(HELPER_SOCKET_PATH);
+
+    if path.exists() {
+        if UnixStream::connect(path).await.is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is in use by another helper", path.display()),
+            ));
+        }
+
+        std::fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+
+    // Unprivileged clients must be able to connect, the commands are limited
+    // to the redirects from the helper settings
+    std::fs::set_permissions(path, Permissions::from_mode(0o666))?;
+
+    info!("Hosts helper listening on {}", path.display());
+    Ok(listener)
+}
+
+/// Handles the requests from a helper connection, entries applied
+/// for the connection are released when it closes
+///
+/// ## Arguments
+/// * `stream` - The connection stream
+/// * `state`  - The shared helper state
+async fn handle_connection<S>(stream: S, state: Arc<Mutex<HelperState>>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (read, mut write) = tokio::io::split(stream);
+    let mut lines = tokio::io::BufReader::new(read).lines();
+    let mut applied = false;
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut response = handle_request(&line, &state, &mut applied).to_string();
+        response.push('\n');
+
+        if write.write_all(response.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+
+    if applied {
+        debug!("Hosts helper client disconnected, releasing host entries");
+        if let Ok(mut state) = state.lock() {
+            state.release();
+        }
+    }
+}
+
+/// Handles a single request `line` providing the response
+///
+/// ## Arguments
+/// * `line`    - The request line
+/// * `state`   - The shared helper state
+/// * `applied` - Whether the connection has applied the entries
+fn handle_request(line: &str, state: &Mutex<HelperState>, applied: &mut bool) -> Value {
+    let request: HelperRequest = match serde_json::from_str(line) {
+        Ok(value) => value,
+        Err(err) => return error_response(format!("Invalid request: {err}")),
+    };
+
+    debug!("Hosts helper request: {:?}", request);
+
+    let Ok(mut state) = state.lock() else {
+        return error_response("Hosts helper state is unavailable");
+    };
+
+    match request {
+        HelperRequest::Apply {
+            redirects,
+            disable_conflicts,
+        } => {
+            // Entries that were removed by another program are applied again
+            if *applied {
+                return match state.reapply(&redirects) {
+                    Ok(existing) => json!({ "ok": true, "existing": existing }),
+                    Err(err) => {
+                        error!("Failed to apply host entries again: {}", err);
+                        error_response(err.to_string())
+                    }
+                };
+            }
+
+            match state.apply(&redirects, disable_conflicts) {
+                Ok(existing) => {
+                    *applied = true;
+                    json!({ "ok": true, "existing": existing })
+                }
+                Err(err) => {
+                    error!("Failed to apply host entries: {}", err);
+                    error_response(err.to_string())
+                }
+            }
+        }
+        HelperRequest::Remove => {
+            if *applied {
+                *applied = false;
+                state.release();
+            }
+
+            json!({ "ok": true })
+        }
+        HelperRequest::Status => {
+            match find_host_entries(&FileBackend::system(), &state.redirects) {
+                Ok(entries) => json!({
+                    "ok": true,
+                    "applied": state.guard.is_some(),
+                    "entries": entries,
+                }),
+                Err(err) => error_response(err.to_string()),
+            }
+        }
+    }
+}
+
+/// Creates an error response
+///
+/// ## Arguments
+/// * `error` - The error message
+fn error_response(error: impl Into<String>) -> Value {
+    json!({ "ok": false, "error": error.into() })
+}
+
+/// Checks whether the `left` and `right` redirects contain the same
+/// redirects ignoring their order
+///
+/// ## Arguments
+/// * `left`  - The first redirects
+/// * `right` - The second redirects
+fn same_redirects(left: &[Redirect], right: &[Redirect]) -> bool {
+    let contains = |redirects: &[Redirect], redirect: &Redirect| {
+        redirects
+            .iter()
+            .any(|other| other.matches(&redirect.host) && other.address == redirect.address)
+    };
+
+    left.iter().all(|redirect| contains(right, redirect))
+        && right.iter().all(|redirect| contains(left, redirect))
+}
+
+/// Describes the `redirects` for error messages
+///
+/// ## Arguments
+/// * `redirects` - The redirects to describe
+fn describe_redirects(redirects: &[Redirect]) -> String {
+    if redirects.is_empty() {
+        return "none".to_string();
+    }
+
+    redirects
+        .iter()
+        .map(|redirect| format!("{} -> {}", redirect.host, redirect.address))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Stream used to connect to the helper
+#[cfg(target_family = "unix")]
+type HelperStream = std::os::unix::net::UnixStream;
+/// Stream used to connect to the helper, named pipes are opened as files
+#[cfg(target_family = "windows")]
+type HelperStream = std::fs::File;
+
+/// Connection from the client to a running helper, the helper releases
+/// the entries applied for the connection when it is closed
+pub struct HelperConnection {
+    /// Reader for the helper responses
+    reader: BufReader<HelperStream>,
+    /// Stream for writing requests
+    writer: HelperStream,
+}
+
+impl HelperConnection {
+    /// Connects to the helper, provides [`None`] if the helper isn't running
+    #[cfg(target_family = "unix")]
+    pub fn connect() -> Option<Self> {
+        let stream = match HelperStream::connect(HELPER_SOCKET_PATH) {
+            Ok(value) => value,
+            Err(err) => {
+                debug!("Hosts helper isn't running: {}", err);
+                return None;
+            }
+        };
+
+        Self::from_stream(stream)
+    }
+
+    /// Connects to the helper, provides [`None`] if the helper isn't running
+    /// or the named pipe wasn't created by a process running as admin
+    #[cfg(target_family = "windows")]
+    pub fn connect() -> Option<Self> {
+        let stream = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(HELPER_PIPE_NAME);
+
+        let stream = match stream {
+            Ok(value) => value,
+            Err(err) => {
+                debug!("Hosts helper isn't running: {}", err);
+                return None;
+            }
+        };
+
+        // Other users could create the pipe before the helper starts
+        if !windows::is_elevated_server(&stream) {
+            warn!("Ignoring hosts helper pipe that wasn't created by an admin process");
+            return None;
+        }
+
+        Self::from_stream(stream)
+    }
+
+    /// Creates a connection using an already connected `stream`
+    ///
+    /// ## Arguments
+    /// * `stream` - The stream connected to the helper
+    pub fn from_stream(stream: HelperStream) -> Option<Self> {
+        #[cfg(target_family = "unix")]
+        stream.set_read_timeout(Some(HELPER_TIMEOUT)).ok()?;
+
+        let writer = stream.try_clone().ok()?;
+        Some(Self {
+            reader: BufReader::new(stream),
+            writer,
+        })
+    }
+
+    /// Starts the helper with admin permissions, the user is asked to allow
+    /// it by the UAC prompt. The helper exits once the client disconnects.
+    /// Provides [`None`] if the helper couldn't be started
+    #[cfg(target_family = "windows")]
+    pub fn start_elevated() -> Option<Self> {
+        use std::time::Instant;
+
+        // The helper uses the same config file for its redirects
+        let parameters = format!(
+            "hosts-helper --exit-when-idle --config \"{}\"",
+            crate::config::config_path().display()
+        );
+
+        if let Err(err) = windows::run_elevated(&parameters) {
+            warn!("Failed to start the hosts helper as admin: {}", err);
+            return None;
+        }
+
+        let start = Instant::now();
+        while start.elapsed() < HELPER_START_TIMEOUT {
+            if let Some(connection) = Self::connect() {
+                debug!("Started hosts helper as admin");
+                return Some(connection);
+            }
+
+            std::thread::sleep(std::time::Duration::from_millis(250));
+        }
+
+        warn!("Timed out waiting for the hosts helper to start");
+        None
+    }
+
+    /// Starts the helper with admin permissions, not applicable on unix
+    /// where the client is started using sudo instead
+    #[cfg(target_family = "unix")]
+    pub fn start_elevated() -> Option<Self> {
+        None
+    }
+
+    /// Asks the helper to apply the entries, provides whether the
+    /// entries already existed
+    ///
+    /// ## Arguments
+    /// * `redirects`         - The redirects to apply
+    /// * `disable_conflicts` - Whether to comment out conflicting entries
+    pub fn apply(
+        &mut self,
+        redirects: &[Redirect],
+        disable_conflicts: bool,
+    ) -> Result<bool, HostsError> {
+        let response = self.request(&HelperRequest::Apply {
+            redirects: redirects.to_vec(),
+            disable_conflicts,
+        })?;
+        Ok(response
+            .get("existing")
+            .and_then(Value::as_bool)
+            .unwrap_or_default())
+    }
+
+    /// Asks the helper to remove the entries applied for this client
+    pub fn remove(&mut self) -> Result<(), HostsError> {
+        self.request(&HelperRequest::Remove)?;
+        Ok(())
+    }
+
+    /// Sends the `request` to the helper and reads the response
+    ///
+    /// ## Arguments
+    /// * `request` - The request to send
+    fn request(&mut self, request: &HelperRequest) -> Result<Value, HostsError> {
+        let mut line = serde_json::to_string(request).map_err(io::Error::from)?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+
+        let mut line = String::new();
+        self.reader.read_line(&mut line)?;
+
+        let response: Value = serde_json::from_str(&line).map_err(io::Error::from)?;
+        if response.get("ok").and_then(Value::as_bool) != Some(true) {
+            let error = response
+                .get("error")
+                .and_then(Value::as_str)
+                .unwrap_or("Unknown error");
+            return Err(HostsError::Helper(error.to_string()));
+        }
+
+        Ok(response)
+    }
+}
+
+#[cfg(target_family = "windows")]
+mod windows {
+    use crate::pipe::to_wide;
+    use std::{
+        ffi::{c_void, OsStr},
+        fs::File,
+        io,
+        os::windows::io::AsRawHandle,
+        ptr::null,
+    };
+    use windows_sys::Win32::{
+        Foundation::{CloseHandle, HANDLE},
+        Security::{GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY},
+        System::{
+            Pipes::GetNamedPipeServerProcessId,
+            Threading::{OpenProcess, OpenProcessToken, PROCESS_QUERY_LIMITED_INFORMATION},
+        },
+        UI::{Shell::ShellExecuteW, WindowsAndMessaging::SW_HIDE},
+    };
+
+    /// Checks whether the process that created the named pipe `stream` is
+    /// running as admin
+    ///
+    /// ## Arguments
+    /// * `stream` - The connected pipe
+    pub fn is_elevated_server(stream: &File) -> bool {
+        let mut process_id = 0;
+        if unsafe { GetNamedPipeServerProcessId(stream.as_raw_handle() as HANDLE, &mut process_id) }
+            == 0
+        {
+            return false;
+        }
+
+        let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, process_id) };
+        if process == 0 {
+            return false;
+        }
+
+        let mut token: HANDLE = 0;
+        let opened = unsafe { OpenProcessToken(process, TOKEN_QUERY, &mut token) } != 0;
+        unsafe { CloseHandle(process) };
+
+        if !opened {
+            return false;
+        }
+
+        let mut elevation = TOKEN_ELEVATION { TokenIsElevated: 0 };
+        let mut length = 0;
+        let result = unsafe {
+            GetTokenInformation(
+                token,
+                TokenElevation,
+                &mut elevation as *mut TOKEN_ELEVATION as *mut c_void,
+                std::mem::size_of::<TOKEN_ELEVATION>() as u32,
+                &mut length,
+            )
+        };
+        unsafe { CloseHandle(token) };
+
+        result != 0 && elevation.TokenIsElevated != 0
+    }
+
+    /// Runs the current executable with admin permissions using the
+    /// provided command line `parameters`
+    ///
+    /// ## Arguments
+    /// * `parameters` - The command line parameters
+    pub fn run_elevated(parameters: &str) -> io::Result<()> {
+        let path = std::env::current_exe()?;
+        let path = to_wide(path.as_os_str());
+        let parameters = to_wide(OsStr::new(parameters));
+        let verb = to_wide(OsStr::new("runas"));
+
+        let result = unsafe {
+            ShellExecuteW(
+                0,
+                verb.as_ptr(),
+                path.as_ptr(),
+                parameters.as_ptr(),
+                null(),
+                SW_HIDE,
+            )
+        };
+
+        // Values greater than 32 indicate success
+        if result <= 32 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
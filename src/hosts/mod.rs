@@ -0,0 +1,1145 @@
+//! Hosts module providing host file modification functionality
+
+use crate::{
+    config::unix_timestamp,
+    ui::{show_confirm, show_warning},
+};
+use log::{debug, error, warn};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
+use std::{
+    borrow::Cow,
+    io::{self, ErrorKind},
+    net::{IpAddr, Ipv4Addr},
+    ops::RangeInclusive,
+    path::Path,
+    process::{self, ExitCode},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+use thiserror::Error;
+
+pub use backend::{FileBackend, HostsBackend};
+pub use helper::run_hosts_helper;
+#[cfg(target_family = "unix")]
+pub use helper::run_private_helper;
+use helper::HelperConnection;
+pub use watcher::watch_entries;
+
+mod backend;
+mod helper;
+#[cfg(test)]
+mod tests;
+mod watcher;
+
+/// The host address redirected by default
+pub const HOST_KEY: &str = "gosredirector.ea.com";
+/// The path to the system hosts file on windows devices
+#[cfg(target_family = "windows")]
+pub const HOSTS_PATH: &str = "C:/Windows/System32/drivers/etc/hosts";
+/// The path to the system hosts file on unix devices
+#[cfg(target_family = "unix")]
+pub const HOSTS_PATH: &str = "/etc/hosts";
+
+/// Line ending used when the hosts file doesn't contain any line endings
+#[cfg(target_family = "windows")]
+const DEFAULT_LINE_ENDING: &[u8] = b"\r\n";
+/// Line ending used when the hosts file doesn't contain any line endings
+#[cfg(target_family = "unix")]
+const DEFAULT_LINE_ENDING: &[u8] = b"\n";
+
+/// Marker line at the start of the block of entries managed by the client
+const BLOCK_BEGIN: &str = "# BEGIN pocket-relay";
+/// Marker line at the end of the block of entries managed by the client
+const BLOCK_END: &str = "# END pocket-relay";
+
+/// Tag added in a comment on entries added by the client, followed by
+/// the process ID and timestamp of the client that added the entry
+const OWNER_TAG: &str = "pocket-relay-client";
+
+/// Tag added to the start of conflicting entries that were commented out by
+/// the client, followed by the process ID of the client and the original
+/// entry (`# pocket-relay-disabled pid=123: 10.0.0.5 gosredirector.ea.com`)
+const DISABLED_TAG: &str = "# pocket-relay-disabled";
+
+/// Whether the host entry was added by the client and must be removed
+/// when the client stops
+static ENTRY_ADDED: AtomicBool = AtomicBool::new(false);
+/// Whether conflicting entries were commented out by the client and must
+/// be restored when the client stops
+static CONFLICTS_DISABLED: AtomicBool = AtomicBool::new(false);
+/// Connection to the hosts helper when the entries were applied by the
+/// helper, the helper removes the entries when it is closed
+static HELPER_CONNECTION: Mutex<Option<HelperConnection>> = Mutex::new(None);
+
+/// Exit code for the status command when the entry isn't present, and for
+/// the apply command when conflicting entries prevent applying it
+const EXIT_NOT_APPLIED: u8 = 1;
+/// Exit code when the hosts file doesn't exist
+const EXIT_FILE_MISSING: u8 = 3;
+/// Exit code when missing permission to modify the hosts file
+const EXIT_PERMISSION_DENIED: u8 = 4;
+/// Exit code when reading or writing the hosts file failed
+const EXIT_IO: u8 = 5;
+
+/// Mapping of a host name to the address it is redirected to
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Redirect {
+    /// The host name to redirect
+    #[serde(deserialize_with = "deserialize_host")]
+    pub host: String,
+    /// The address to redirect the host to
+    pub address: IpAddr,
+}
+
+impl Redirect {
+    /// Provides the default redirects, gosredirector.ea.com to localhost
+    pub fn defaults() -> Vec<Redirect> {
+        vec![Redirect {
+            host: HOST_KEY.to_string(),
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+        }]
+    }
+
+    /// Checks whether the provided `host` name is the redirected host,
+    /// host names are matched case-insensitively
+    ///
+    /// ## Arguments
+    /// * `host` - The host name to check
+    pub fn matches(&self, host: &str) -> bool {
+        host.eq_ignore_ascii_case(&self.host)
+    }
+}
+
+/// Deserializes a host name ensuring it only contains characters that
+/// are valid within the hosts file
+fn deserialize_host<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let host = String::deserialize(deserializer)?;
+    let valid = !host.is_empty()
+        && host
+            .chars()
+            .all(|value| value.is_ascii_alphanumeric() || matches!(value, '-' | '.' | '_'));
+
+    if !valid {
+        return Err(D::Error::custom(format!("invalid host name \"{host}\"")));
+    }
+
+    Ok(host)
+}
+
+/// Hosts file commands that can be run from the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostsCommand {
+    /// Adds the host entry
+    Apply,
+    /// Removes the host entry
+    Remove,
+    /// Prints whether the host entry is present
+    Status,
+}
+
+impl FromStr for HostsCommand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "apply" => Ok(Self::Apply),
+            "remove" => Ok(Self::Remove),
+            "status" => Ok(Self::Status),
+            _ => Err(format!(
+                "unknown hosts command \"{s}\" expected apply, remove or status"
+            )),
+        }
+    }
+}
+
+/// Errors that could occur while working with the hosts file
+#[derive(Debug, Error)]
+pub enum HostsError {
+    /// Hosts file doesn't exist
+    #[error("Missing system hosts file")]
+    FileMissing,
+    /// Missing admin permission to access file
+    #[error(
+        "Missing permission to modify hosts file. Ensure this program is running as admin \
+        or that the hosts helper is running (pocket-relay-client hosts-helper)"
+    )]
+    PermissionsError,
+    /// Failed to read the hosts file
+    #[error(transparent)]
+    IO(io::Error),
+    /// Hosts helper failed to handle a request
+    #[error("Hosts helper error: {0}")]
+    Helper(String),
+    /// Redirects from the client don't match the redirects managed by the
+    /// hosts helper
+    #[error(
+        "The hosts helper manages different redirects ({0}) than the client ({1}), \
+        use the same redirects in the settings for both"
+    )]
+    RedirectMismatch(String, String),
+    /// Entries redirecting the hosts to a different address are present
+    #[error(
+        "Your hosts file redirects the hosts to a different address which will prevent \
+        the game from connecting through the client:\n\n{0}"
+    )]
+    ConflictingEntries(String),
+}
+
+impl HostsError {
+    /// Provides the message shown for the error, missing permissions include
+    /// the entries for the `redirects` that can be added manually instead
+    ///
+    /// ## Arguments
+    /// * `redirects` - The redirects that were being applied
+    pub fn message(&self, redirects: &[Redirect]) -> String {
+        let mut text = self.to_string();
+
+        if let Self::PermissionsError = self {
+            let entries = redirects
+                .iter()
+                .map(|redirect| format!("{} {}", redirect.address, redirect.host))
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            text.push_str(&format!(
+                "\n\nYou can ignore this warning if you have manually modified your \
+                hosts file to include the following entries:\n\n{entries}"
+            ));
+        }
+
+        text
+    }
+}
+
+/// Uses the `stream` connected to the hosts helper forked by the client
+/// before it dropped its root privileges to apply the entries
+///
+/// ## Arguments
+/// * `stream` - The stream connected to the helper
+#[cfg(target_family = "unix")]
+pub fn set_helper_stream(stream: std::os::unix::net::UnixStream) {
+    let Some(connection) = HelperConnection::from_stream(stream) else {
+        return;
+    };
+
+    if let Ok(mut value) = HELPER_CONNECTION.lock() {
+        *value = Some(connection);
+    }
+}
+
+/// Guard structure that applies the host file entry then
+/// removes the host entry once the guard is dropped
+pub struct HostEntryGuard {
+    /// Whether the entry already existed
+    /// (The cleanup won't remove the entry on drop)
+    existing: bool,
+}
+
+impl HostEntryGuard {
+    /// Attempts to apply the [`HostEntryGuard`] returning the guard
+    /// on success
+    ///
+    /// ## Arguments
+    /// * `redirects` - The redirects to apply
+    pub fn apply(redirects: &[Redirect]) -> Option<Self> {
+        if redirects.is_empty() {
+            debug!("No redirects to apply");
+            return None;
+        }
+
+        let backend = FileBackend::system();
+
+        // Prefer the helper so the client doesn't need to run as admin, the
+        // helper is started as admin when the hosts file isn't writable
+        let connection = HELPER_CONNECTION
+            .lock()
+            .ok()
+            .and_then(|mut value| value.take())
+            .or_else(HelperConnection::connect)
+            .or_else(|| {
+                if backend.is_writable() {
+                    None
+                } else {
+                    HelperConnection::start_elevated()
+                }
+            });
+
+        if let Some(connection) = connection {
+            return Self::apply_helper(&backend, redirects, connection);
+        }
+
+        // Conflicts left commented out by a client that crashed are restored
+        Self::restore_orphans(&backend);
+        Self::resolve_conflicts(&backend, redirects);
+
+        match Self::apply_entry(&backend, redirects) {
+            Ok(value) => {
+                if value.existing {
+                    debug!("Host modification already applied");
+                } else {
+                    debug!("Applied host modification");
+                }
+                Some(value)
+            }
+            Err(err) => {
+                show_warning("Failed to apply host modification", &err.message(redirects));
+                warn!("Failed to apply host entry: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Asks the hosts helper to apply the entries, the helper keeps the
+    /// entries applied until the connection is closed
+    ///
+    /// ## Arguments
+    /// * `backend`    - The hosts file backend used to check for conflicts
+    /// * `redirects`  - The redirects to check for conflicts
+    /// * `connection` - The connection to the helper
+    fn apply_helper(
+        backend: &dyn HostsBackend,
+        redirects: &[Redirect],
+        mut connection: HelperConnection,
+    ) -> Option<Self> {
+        let disable_conflicts = Self::confirm_conflicts(backend, redirects);
+
+        match connection.apply(redirects, disable_conflicts) {
+            Ok(existing) => {
+                debug!("Applied host modification using the hosts helper");
+                if let Ok(mut value) = HELPER_CONNECTION.lock() {
+                    *value = Some(connection);
+                }
+                Some(Self { existing })
+            }
+            Err(err) => {
+                show_warning("Failed to apply host modification", &err.message(redirects));
+                warn!("Failed to apply host entry using the hosts helper: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Checks the hosts file for entries redirecting the hosts to a different
+    /// address, the user is asked whether the entries should be commented out
+    /// while the client is running
+    ///
+    /// ## Arguments
+    /// * `backend`   - The hosts file backend
+    /// * `redirects` - The redirects to check against
+    fn resolve_conflicts(backend: &dyn HostsBackend, redirects: &[Redirect]) {
+        if !Self::confirm_conflicts(backend, redirects) {
+            return;
+        }
+
+        match Self::disable_conflicts(backend, redirects) {
+            Ok(_) => {
+                CONFLICTS_DISABLED.store(true, Ordering::SeqCst);
+                debug!("Disabled conflicting host entries");
+            }
+            Err(err) => {
+                show_warning("Failed to disable conflicting entries", &err.to_string());
+                error!("Failed to disable conflicting host entries: {}", err);
+            }
+        }
+    }
+
+    /// Finds entries redirecting the hosts to a different address and asks
+    /// the user whether they should be commented out, provides whether the
+    /// conflicting entries should be disabled
+    ///
+    /// ## Arguments
+    /// * `backend`   - The hosts file backend
+    /// * `redirects` - The redirects to check against
+    fn confirm_conflicts(backend: &dyn HostsBackend, redirects: &[Redirect]) -> bool {
+        let conflicts = match find_conflicts(backend, redirects) {
+            Ok(value) => value,
+            Err(err) => {
+                // Errors are reported when applying the entry
+                debug!("Failed to check for conflicting host entries: {}", err);
+                return false;
+            }
+        };
+
+        if conflicts.is_empty() {
+            return false;
+        }
+
+        for line in &conflicts {
+            warn!("Conflicting host entry: {}", line.trim());
+        }
+
+        let text = format!(
+            "Your hosts file redirects {} to a different address which will prevent \
+            the game from connecting through the client:\n\n{}\n\n\
+            Would you like to comment out these entries while the client is running? \
+            They will be restored when the client is closed",
+            redirects
+                .iter()
+                .map(|redirect| redirect.host.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            conflicts
+                .iter()
+                .map(|line| line.trim())
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+
+        show_confirm("Conflicting hosts entry", &text)
+    }
+
+    /// Comments out the entries redirecting the hosts to a different
+    /// address, returns the lines that were commented out
+    ///
+    /// ## Arguments
+    /// * `backend`   - The hosts file backend
+    /// * `redirects` - The redirects to check against
+    fn disable_conflicts(
+        backend: &dyn HostsBackend,
+        redirects: &[Redirect],
+    ) -> Result<Vec<String>, HostsError> {
+        let host_file = backend.read()?;
+        let prefix = format!("{DISABLED_TAG} pid={}: ", std::process::id());
+        let mut disabled = Vec::new();
+        let mut output = Vec::with_capacity(host_file.len());
+
+        for line in split_lines(&host_file) {
+            let text = line_text(line);
+            if is_conflicting_line(&text, redirects) {
+                output.extend_from_slice(prefix.as_bytes());
+                disabled.push(text.into_owned());
+            }
+            output.extend_from_slice(line);
+        }
+
+        if !disabled.is_empty() {
+            backend.write(&host_file, &output)?;
+        }
+
+        Ok(disabled)
+    }
+
+    /// Restores the entries that were commented out by the client, returns
+    /// the lines that were restored
+    ///
+    /// ## Arguments
+    /// * `backend` - The hosts file backend
+    fn restore_conflicts(backend: &dyn HostsBackend) -> Result<Vec<String>, HostsError> {
+        Self::restore_disabled(backend, |_| true)
+    }
+
+    /// Restores the entries that were commented out by clients that are no
+    /// longer running (i.e. the client crashed), returns the lines that
+    /// were restored
+    ///
+    /// ## Arguments
+    /// * `backend` - The hosts file backend
+    fn restore_orphaned_conflicts(backend: &dyn HostsBackend) -> Result<Vec<String>, HostsError> {
+        Self::restore_disabled(backend, |pid| {
+            // Entries without a process ID were disabled by older clients
+            pid.is_none_or(|pid| !is_client_process(pid))
+        })
+    }
+
+    /// Restores the orphaned conflicting entries before the entries are
+    /// applied, failures are ignored as they're reported when applying
+    ///
+    /// ## Arguments
+    /// * `backend` - The hosts file backend
+    fn restore_orphans(backend: &dyn HostsBackend) {
+        match Self::restore_orphaned_conflicts(backend) {
+            Ok(restored) => {
+                for line in restored {
+                    warn!(
+                        "Restored conflicting host entry left disabled by a client that isn't running: {}",
+                        line.trim()
+                    );
+                }
+            }
+            Err(err) => debug!(
+                "Failed to restore orphaned conflicting host entries: {}",
+                err
+            ),
+        }
+    }
+
+    /// Restores the commented out entries where `should_restore` returns true
+    /// for the process ID of the client that commented them out, returns the
+    /// lines that were restored
+    ///
+    /// ## Arguments
+    /// * `backend`        - The hosts file backend
+    /// * `should_restore` - Whether to restore entries disabled by a process ID
+    fn restore_disabled(
+        backend: &dyn HostsBackend,
+        should_restore: fn(Option<u32>) -> bool,
+    ) -> Result<Vec<String>, HostsError> {
+        let host_file = backend.read()?;
+        let mut restored = Vec::new();
+        let mut output = Vec::with_capacity(host_file.len());
+
+        for line in split_lines(&host_file) {
+            match parse_disabled(line) {
+                Some((pid, original)) if should_restore(pid) => {
+                    restored.push(line_text(original).into_owned());
+                    output.extend_from_slice(original);
+                }
+                _ => output.extend_from_slice(line),
+            }
+        }
+
+        if !restored.is_empty() {
+            backend.write(&host_file, &output)?;
+        }
+
+        Ok(restored)
+    }
+
+    /// Adds the redirect entries to the hosts file
+    ///
+    /// ## Arguments
+    /// * `backend`   - The hosts file backend
+    /// * `redirects` - The redirects to add
+    fn apply_entry(backend: &dyn HostsBackend, redirects: &[Redirect]) -> Result<Self, HostsError> {
+        let existing = Self::add_entry(backend, redirects, true)?;
+        if !existing {
+            ENTRY_ADDED.store(true, Ordering::SeqCst);
+        }
+
+        Ok(Self { existing })
+    }
+
+    /// Adds the entries for the `redirects` to the hosts file inside the managed
+    /// block if they're not already present, returns whether the entries already
+    /// existed. Only entries redirecting to the configured address exist, other
+    /// entries for the hosts are reported as conflicts. Entries left behind by
+    /// clients that are no longer running are replaced
+    ///
+    /// ## Arguments
+    /// * `backend`   - The hosts file backend
+    /// * `redirects` - The redirects to add
+    /// * `owned`     - Whether to tag the entries as owned by this client process
+    fn add_entry(
+        backend: &dyn HostsBackend,
+        redirects: &[Redirect],
+        owned: bool,
+    ) -> Result<bool, HostsError> {
+        let host_file = backend.read()?;
+        let lines = split_lines(&host_file);
+        let texts: Vec<Cow<str>> = lines.iter().map(|line| line_text(line)).collect();
+        let blocks = find_blocks(&texts);
+        let in_block = |index: usize| blocks.iter().any(|block| block.contains(&index));
+
+        // Entries outside the managed block were added by the user so are left alone
+        let user_entries: Vec<&str> = texts
+            .iter()
+            .enumerate()
+            .filter(|(index, line)| !in_block(*index) && Self::is_host_line(line, redirects))
+            .map(|(_, line)| line.as_ref())
+            .collect();
+
+        let missing: Vec<&Redirect> = redirects
+            .iter()
+            .filter(|redirect| {
+                !user_entries
+                    .iter()
+                    .any(|line| is_redirect_line(line, redirect))
+            })
+            .collect();
+
+        if missing.is_empty() {
+            return Ok(true);
+        }
+
+        // User entries redirecting to a different address take priority over the block
+        let conflicts: Vec<&str> = user_entries
+            .iter()
+            .copied()
+            .filter(|line| {
+                missing
+                    .iter()
+                    .any(|redirect| is_conflicting_line(line, std::slice::from_ref(*redirect)))
+            })
+            .collect();
+
+        if !conflicts.is_empty() {
+            return Err(HostsError::ConflictingEntries(conflicts.join("\n")));
+        }
+
+        // Find existing managed entries if present
+        let entries: Vec<&str> = texts
+            .iter()
+            .enumerate()
+            .filter(|(index, line)| in_block(*index) && Self::is_host_line(line, redirects))
+            .map(|(_, line)| line.as_ref())
+            .collect();
+
+        if !entries.is_empty() {
+            // Entries are orphaned when all of them were added by clients that aren't running
+            let orphaned = entries.iter().all(|line| {
+                EntryOwner::parse(line).is_some_and(|owner| !is_client_process(owner.pid))
+            });
+
+            if !orphaned {
+                // Entries from another running client only exist when they
+                // redirect to the configured addresses
+                let existing = missing
+                    .iter()
+                    .all(|redirect| entries.iter().any(|line| is_redirect_line(line, redirect)));
+
+                if existing {
+                    return Ok(true);
+                }
+
+                return Err(HostsError::ConflictingEntries(entries.join("\n")));
+            }
+
+            for line in &entries {
+                if let Some(owner) = EntryOwner::parse(line) {
+                    warn!(
+                        "Reclaiming host entry left by client process {} ({}s ago)",
+                        owner.pid,
+                        unix_timestamp().saturating_sub(owner.timestamp)
+                    );
+                }
+            }
+        }
+
+        let line_ending = line_ending(&lines);
+        let mut output: Vec<u8> = lines
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !in_block(*index))
+            .flat_map(|(_, line)| line.iter().copied())
+            .collect();
+
+        // Last line may be missing its line ending
+        if !output.is_empty() && !output.ends_with(b"\n") {
+            output.extend_from_slice(line_ending);
+        }
+
+        let host_lines = missing.into_iter().map(|redirect| {
+            if owned {
+                format!(
+                    "{} {} # {} pid={} time={}",
+                    redirect.address,
+                    redirect.host,
+                    OWNER_TAG,
+                    process::id(),
+                    unix_timestamp()
+                )
+            } else {
+                format!("{} {}", redirect.address, redirect.host)
+            }
+        });
+
+        let lines = std::iter::once(BLOCK_BEGIN.to_string())
+            .chain(host_lines)
+            .chain(std::iter::once(BLOCK_END.to_string()));
+
+        for line in lines {
+            output.extend_from_slice(line.as_bytes());
+            output.extend_from_slice(line_ending);
+        }
+
+        backend.write(&host_file, &output)?;
+
+        Ok(false)
+    }
+
+    /// Removes the managed block containing the redirect entries from the
+    /// hosts file returning the entry lines that were removed. Entries
+    /// outside the managed block are left alone
+    ///
+    /// ## Arguments
+    /// * `backend` - The hosts file backend
+    fn remove_entry(backend: &dyn HostsBackend) -> Result<Vec<String>, HostsError> {
+        let host_file = backend.read()?;
+        let lines = split_lines(&host_file);
+        let texts: Vec<Cow<str>> = lines.iter().map(|line| line_text(line)).collect();
+        let blocks = find_blocks(&texts);
+
+        if blocks.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let in_block = |index: usize| blocks.iter().any(|block| block.contains(&index));
+
+        let output: Vec<u8> = lines
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !in_block(*index))
+            .flat_map(|(_, line)| line.iter().copied())
+            .collect();
+
+        backend.write(&host_file, &output)?;
+
+        Ok(texts
+            .into_iter()
+            .enumerate()
+            .filter(|(index, line)| in_block(*index) && parse_entry(line).is_some())
+            .map(|(_, line)| line.into_owned())
+            .collect())
+    }
+
+    /// Checks whether the hosts file line `value` is an entry for any of the
+    /// `redirects` hosts, host names are matched case-insensitively including
+    /// aliases and commented out entries are ignored
+    ///
+    /// ## Arguments
+    /// * `value`     - The hosts file line
+    /// * `redirects` - The redirects to match
+    pub fn is_host_line(value: &str, redirects: &[Redirect]) -> bool {
+        parse_entry(value).is_some_and(|(_, hosts)| {
+            hosts
+                .iter()
+                .any(|host| redirects.iter().any(|redirect| redirect.matches(host)))
+        })
+    }
+}
+
+impl Drop for HostEntryGuard {
+    fn drop(&mut self) {
+        // Entries that existed before we started aren't removed by the cleanup
+        cleanup();
+    }
+}
+
+/// Splits the hosts file line `value` into its address and host names
+/// ignoring comments, provides [`None`] for lines without an entry
+///
+/// ## Arguments
+/// * `value` - The hosts file line
+fn parse_entry(value: &str) -> Option<(&str, Vec<&str>)> {
+    let value = value
+        .split_once('#')
+        // Take the first half if present
+        .map(|(before, _)| before)
+        // Take entire line of not containing a comment
+        .unwrap_or(value);
+
+    let mut parts = value.split_whitespace();
+    let address = parts.next()?;
+    let hosts: Vec<&str> = parts.collect();
+
+    if hosts.is_empty() {
+        return None;
+    }
+
+    Some((address, hosts))
+}
+
+/// Checks whether the hosts file line `value` is an entry redirecting the
+/// `redirect` host to its configured address
+///
+/// ## Arguments
+/// * `value`    - The hosts file line
+/// * `redirect` - The redirect to match
+pub fn is_redirect_line(value: &str, redirect: &Redirect) -> bool {
+    parse_entry(value).is_some_and(|(address, hosts)| {
+        address.parse::<IpAddr>().ok() == Some(redirect.address)
+            && hosts.iter().any(|host| redirect.matches(host))
+    })
+}
+
+/// Checks whether the hosts file line `value` is an entry redirecting
+/// any of the `redirects` hosts to a different address
+///
+/// ## Arguments
+/// * `value`     - The hosts file line
+/// * `redirects` - The redirects to check against
+pub fn is_conflicting_line(value: &str, redirects: &[Redirect]) -> bool {
+    let Some((address, hosts)) = parse_entry(value) else {
+        return false;
+    };
+
+    let address = address.parse::<IpAddr>().ok();
+
+    hosts.iter().any(|host| {
+        redirects
+            .iter()
+            .any(|redirect| redirect.matches(host) && address != Some(redirect.address))
+    })
+}
+
+/// Finds the entries in the hosts file redirecting any of the `redirects`
+/// hosts to a different address
+///
+/// ## Arguments
+/// * `backend`   - The hosts file backend
+/// * `redirects` - The redirects to check against
+fn find_conflicts(
+    backend: &dyn HostsBackend,
+    redirects: &[Redirect],
+) -> Result<Vec<String>, HostsError> {
+    let entries = find_host_entries(backend, redirects)?
+        .into_iter()
+        .filter(|line| is_conflicting_line(line, redirects))
+        .collect();
+    Ok(entries)
+}
+
+/// Finds the ranges of the managed blocks in the hosts file `lines`
+/// including the marker lines, markers without a matching start or
+/// end marker are ignored
+///
+/// ## Arguments
+/// * `lines` - The lines of the hosts file
+fn find_blocks<T: AsRef<str>>(lines: &[T]) -> Vec<RangeInclusive<usize>> {
+    let mut blocks = Vec::new();
+    let mut start = None;
+
+    for (index, line) in lines.iter().enumerate() {
+        match line.as_ref().trim() {
+            BLOCK_BEGIN => start = Some(index),
+            BLOCK_END => {
+                if let Some(start) = start.take() {
+                    blocks.push(start..=index);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    blocks
+}
+
+/// Splits the hosts file `bytes` into lines keeping the line endings
+/// so the lines can be written back unchanged
+///
+/// ## Arguments
+/// * `bytes` - The hosts file contents
+fn split_lines(bytes: &[u8]) -> Vec<&[u8]> {
+    bytes.split_inclusive(|value| *value == b'\n').collect()
+}
+
+/// Provides the text of a hosts file `line` without the line ending,
+/// invalid UTF-8 is replaced as its only used for matching entries
+///
+/// ## Arguments
+/// * `line` - The line including the line ending
+fn line_text(line: &[u8]) -> Cow<'_, str> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    String::from_utf8_lossy(line)
+}
+
+/// Provides the line ending used by the hosts file `lines` falling
+/// back to the platform line ending
+///
+/// ## Arguments
+/// * `lines` - The lines of the hosts file
+fn line_ending(lines: &[&[u8]]) -> &'static [u8] {
+    match lines.iter().find(|line| line.ends_with(b"\n")) {
+        Some(line) if line.ends_with(b"\r\n") => b"\r\n",
+        Some(_) => b"\n",
+        None => DEFAULT_LINE_ENDING,
+    }
+}
+
+/// Checks whether the hosts file `bytes` contain changes made by the client
+/// (A managed block or commented out conflicts)
+///
+/// ## Arguments
+/// * `bytes` - The hosts file contents
+fn has_client_changes(bytes: &[u8]) -> bool {
+    split_lines(bytes)
+        .into_iter()
+        .any(|line| parse_disabled(line).is_some() || line_text(line).trim() == BLOCK_BEGIN)
+}
+
+/// Parses a conflicting entry `line` that was commented out by the client,
+/// provides the process ID of the client that commented it out (Older
+/// clients didn't include it) and the original line
+///
+/// ## Arguments
+/// * `line` - The hosts file line
+fn parse_disabled(line: &[u8]) -> Option<(Option<u32>, &[u8])> {
+    let line = line.strip_prefix(DISABLED_TAG.as_bytes())?;
+    let split = line.iter().position(|value| *value == b':')?;
+    let (tag, original) = line.split_at(split);
+    let original = &original[1..];
+    let original = original.strip_prefix(b" ").unwrap_or(original);
+
+    let pid = match std::str::from_utf8(tag).ok()?.trim() {
+        "" => None,
+        tag => Some(tag.strip_prefix("pid=")?.parse().ok()?),
+    };
+
+    Some((pid, original))
+}
+
+/// Client process that added a host entry, parsed from the tag
+/// in the comment of the entry
+struct EntryOwner {
+    /// Process ID of the client
+    pid: u32,
+    /// Unix timestamp in seconds of when the entry was added
+    timestamp: u64,
+}
+
+impl EntryOwner {
+    /// Parses the owner from the comment of a host entry `line`, entries
+    /// without the owner tag weren't added by the client
+    ///
+    /// ## Arguments
+    /// * `line` - The host entry line
+    fn parse(line: &str) -> Option<Self> {
+        let (_, comment) = line.split_once('#')?;
+        let mut parts = comment.split_whitespace();
+        if parts.next()? != OWNER_TAG {
+            return None;
+        }
+
+        let mut pid = None;
+        let mut timestamp = None;
+
+        for part in parts {
+            match part.split_once('=') {
+                Some(("pid", value)) => pid = value.parse().ok(),
+                Some(("time", value)) => timestamp = value.parse().ok(),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            pid: pid?,
+            timestamp: timestamp.unwrap_or_default(),
+        })
+    }
+}
+
+/// Checks whether the process with the provided `pid` is a running
+/// client, uses the process name to ignore reused process IDs. Assumes
+/// the process is running if it can't be determined
+///
+/// ## Arguments
+/// * `pid` - The process ID
+#[cfg(target_family = "unix")]
+fn is_client_process(pid: u32) -> bool {
+    if !Path::new("/proc/self").exists() {
+        return true;
+    }
+
+    match (
+        std::fs::read_to_string(format!("/proc/{pid}/comm")),
+        std::fs::read_to_string("/proc/self/comm"),
+    ) {
+        (Ok(name), Ok(current)) => name.trim() == current.trim(),
+        // Process isn't running
+        (Err(_), _) => false,
+        (_, Err(_)) => true,
+    }
+}
+
+/// Checks whether the process with the provided `pid` is a running
+/// client, uses the process name to ignore reused process IDs. Assumes
+/// the process is running if it can't be determined
+///
+/// ## Arguments
+/// * `pid` - The process ID
+#[cfg(target_family = "windows")]
+fn is_client_process(pid: u32) -> bool {
+    use std::{os::windows::process::CommandExt, process::Command};
+
+    /// Prevents a console window from being shown for the command
+    const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+    let Some(current) = std::env::current_exe().ok().and_then(|path| {
+        path.file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+    }) else {
+        return true;
+    };
+
+    let output = Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {pid}"), "/FO", "CSV", "/NH"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output();
+
+    match output {
+        Ok(output) => String::from_utf8_lossy(&output.stdout)
+            .to_lowercase()
+            .contains(&format!("\"{current}\"")),
+        Err(_) => true,
+    }
+}
+
+/// Removes the host entry if it was added by the client and restores any
+/// conflicting entries that were commented out, entries applied by the hosts
+/// helper are released. Safe to call multiple times as the changes are only
+/// reverted once
+pub fn cleanup() {
+    // Stop the watcher so the entries aren't applied again once removed
+    watcher::stop_watching();
+
+    let connection = HELPER_CONNECTION
+        .lock()
+        .ok()
+        .and_then(|mut value| value.take());
+    if let Some(mut connection) = connection {
+        if let Err(err) = connection.remove() {
+            error!(
+                "Failed to remove host entry using the hosts helper: {}",
+                err
+            );
+        } else {
+            debug!("Removed host modification using the hosts helper")
+        }
+    }
+
+    let backend = FileBackend::system();
+
+    if ENTRY_ADDED.swap(false, Ordering::SeqCst) {
+        if let Err(err) = HostEntryGuard::remove_entry(&backend) {
+            error!("Failed to remove host entry: {}", err);
+        } else {
+            debug!("Removed host modification")
+        }
+    }
+
+    if CONFLICTS_DISABLED.swap(false, Ordering::SeqCst) {
+        if let Err(err) = HostEntryGuard::restore_conflicts(&backend) {
+            error!("Failed to restore conflicting host entries: {}", err);
+        } else {
+            debug!("Restored conflicting host entries")
+        }
+    }
+}
+
+/// Checks whether the hosts file contains entries added by another
+/// client process that is still running
+///
+/// ## Arguments
+/// * `backend` - The hosts file backend
+pub fn has_running_client(backend: &dyn HostsBackend) -> bool {
+    let Ok(host_file) = backend.read() else {
+        return false;
+    };
+
+    let current = std::process::id();
+
+    split_lines(&host_file)
+        .into_iter()
+        .filter_map(|line| EntryOwner::parse(&line_text(line)))
+        .any(|owner| owner.pid != current && is_client_process(owner.pid))
+}
+
+/// Finds the entries for the `redirects` hosts in the hosts file
+///
+/// ## Arguments
+/// * `backend`   - The hosts file backend
+/// * `redirects` - The redirects to find entries for
+pub fn find_host_entries(
+    backend: &dyn HostsBackend,
+    redirects: &[Redirect],
+) -> Result<Vec<String>, HostsError> {
+    let host_file = backend.read()?;
+    let entries = split_lines(&host_file)
+        .into_iter()
+        .map(line_text)
+        .filter(|line| HostEntryGuard::is_host_line(line, redirects))
+        .map(Cow::into_owned)
+        .collect();
+    Ok(entries)
+}
+
+/// Runs a hosts file `command` printing what was changed, provides
+/// the exit code for the command
+///
+/// ## Arguments
+/// * `command`   - The command to run
+/// * `redirects` - The redirects to manage
+pub fn run_hosts_command(command: HostsCommand, redirects: &[Redirect]) -> ExitCode {
+    let backend = FileBackend::system();
+    let hosts = redirects
+        .iter()
+        .map(|redirect| redirect.host.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let result = match command {
+        HostsCommand::Apply => {
+            HostEntryGuard::add_entry(&backend, redirects, false).map(|existing| {
+                if existing {
+                    println!("Hosts entries for {hosts} are already present in {HOSTS_PATH}");
+                } else {
+                    println!("Added hosts entries for {hosts} to {HOSTS_PATH}");
+                }
+
+                ExitCode::SUCCESS
+            })
+        }
+        HostsCommand::Remove => HostEntryGuard::remove_entry(&backend).and_then(|removed| {
+            // Entries commented out by a client that didn't exit cleanly are restored
+            let restored = HostEntryGuard::restore_conflicts(&backend)?;
+
+            if removed.is_empty() {
+                println!("No hosts entries managed by the client found in {HOSTS_PATH}");
+            }
+
+            for line in removed {
+                println!("Removed hosts entry \"{}\" from {HOSTS_PATH}", line.trim());
+            }
+
+            for line in restored {
+                println!("Restored hosts entry \"{}\" in {HOSTS_PATH}", line.trim());
+            }
+
+            Ok(ExitCode::SUCCESS)
+        }),
+        HostsCommand::Status => find_host_entries(&backend, redirects).map(|entries| {
+            for line in &entries {
+                if is_conflicting_line(line, redirects) {
+                    println!("Conflicting hosts entry present: {}", line.trim());
+                } else {
+                    println!("Hosts entry present: {}", line.trim());
+                }
+            }
+
+            // Redirects without an entry redirecting to the expected address
+            let missing: Vec<&Redirect> = redirects
+                .iter()
+                .filter(|redirect| !entries.iter().any(|line| is_redirect_line(line, redirect)))
+                .collect();
+
+            for redirect in &missing {
+                println!(
+                    "Hosts entry for {} is not present in {HOSTS_PATH}",
+                    redirect.host
+                );
+            }
+
+            if missing.is_empty() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::from(EXIT_NOT_APPLIED)
+            }
+        }),
+    };
+
+    result.unwrap_or_else(|err| {
+        eprintln!("{}", err.message(redirects));
+        ExitCode::from(match err {
+            HostsError::FileMissing => EXIT_FILE_MISSING,
+            HostsError::PermissionsError => EXIT_PERMISSION_DENIED,
+            HostsError::ConflictingEntries(_) => EXIT_NOT_APPLIED,
+            HostsError::IO(_) | HostsError::Helper(_) | HostsError::RedirectMismatch(..) => EXIT_IO,
+        })
+    })
+}
+
+impl From<io::Error> for HostsError {
+    fn from(value: io::Error) -> Self {
+        match value.kind() {
+            ErrorKind::PermissionDenied => HostsError::PermissionsError,
+            _ => HostsError::IO(value),
+        }
+    }
+}
@@ -0,0 +1,480 @@
+use super::{backend::MemoryBackend, *};
+use std::{
+    env::temp_dir,
+    fs::{read, remove_file, write},
+};
+
+/// Address of the default redirect
+const HOST_VALUE: &str = "127.0.0.1";
+/// Process ID that won't belong to a running process
+const DEAD_PID: u32 = u32::MAX;
+
+/// Checks whether `value` is an entry for the default redirects
+///
+/// ## Arguments
+/// * `value` - The hosts file line
+fn is_host_line(value: &str) -> bool {
+    HostEntryGuard::is_host_line(value, &Redirect::defaults())
+}
+
+/// Provides the managed block that [`HostEntryGuard::add_entry`] writes
+/// when the entry isn't owned by the client
+///
+/// ## Arguments
+/// * `line_ending` - The line ending to use
+fn unowned_block(line_ending: &str) -> String {
+    [BLOCK_BEGIN, &format!("{HOST_VALUE} {HOST_KEY}"), BLOCK_END]
+        .iter()
+        .map(|line| format!("{line}{line_ending}"))
+        .collect()
+}
+
+#[test]
+fn host_line_matches_entries() {
+    assert!(is_host_line("127.0.0.1 gosredirector.ea.com"));
+    assert!(is_host_line("  127.0.0.1\tgosredirector.ea.com  "));
+    assert!(is_host_line("127.0.0.1 gosredirector.ea.com # comment"));
+    assert!(is_host_line("127.0.0.1 gosredirector.ea.com#comment"));
+    assert!(is_host_line("127.0.0.1 GOSREDIRECTOR.EA.COM"));
+    assert!(is_host_line("127.0.0.1 localhost gosredirector.ea.com"));
+    assert!(is_host_line("::1 gosredirector.ea.com"));
+}
+
+#[test]
+fn host_line_ignores_other_lines() {
+    assert!(!is_host_line(""));
+    assert!(!is_host_line("   "));
+    assert!(!is_host_line("# 127.0.0.1 gosredirector.ea.com"));
+    assert!(!is_host_line("#127.0.0.1 gosredirector.ea.com"));
+    assert!(!is_host_line("127.0.0.1 localhost # gosredirector.ea.com"));
+    assert!(!is_host_line("gosredirector.ea.com"));
+    assert!(!is_host_line("gosredirector.ea.com 127.0.0.1"));
+    assert!(!is_host_line("127.0.0.1 gosredirector.ea.com.example"));
+    assert!(!is_host_line("127.0.0.1 xgosredirector.ea.com"));
+}
+
+#[test]
+fn add_entry_appends_block() {
+    let backend = MemoryBackend::new(b"127.0.0.1 localhost\n");
+
+    let existing = HostEntryGuard::add_entry(&backend, &Redirect::defaults(), false).unwrap();
+
+    assert!(!existing);
+    assert_eq!(
+        String::from_utf8(backend.contents()).unwrap(),
+        format!("127.0.0.1 localhost\n{}", unowned_block("\n"))
+    );
+    assert_eq!(
+        backend.backup.borrow().as_deref(),
+        Some(b"127.0.0.1 localhost\n".as_slice())
+    );
+}
+
+#[test]
+fn backup_keeps_original_contents() {
+    let contents = b"127.0.0.1 localhost\n";
+    let backend = MemoryBackend::new(contents);
+
+    HostEntryGuard::add_entry(&backend, &Redirect::defaults(), false).unwrap();
+    HostEntryGuard::remove_entry(&backend).unwrap();
+
+    assert_eq!(backend.contents(), contents);
+    assert_eq!(
+        backend.backup.borrow().as_deref(),
+        Some(contents.as_slice())
+    );
+}
+
+#[test]
+fn add_entry_tags_owned_entry() {
+    let backend = MemoryBackend::new(b"");
+
+    HostEntryGuard::add_entry(&backend, &Redirect::defaults(), true).unwrap();
+
+    let contents = String::from_utf8(backend.contents()).unwrap();
+    let entry = contents.lines().find(|line| is_host_line(line)).unwrap();
+    let owner = EntryOwner::parse(entry).unwrap();
+    assert_eq!(owner.pid, process::id());
+}
+
+#[test]
+fn add_entry_keeps_user_entry() {
+    let contents = b"127.0.0.1 localhost\n127.0.0.1 gosredirector.ea.com\n";
+    let backend = MemoryBackend::new(contents);
+
+    let existing = HostEntryGuard::add_entry(&backend, &Redirect::defaults(), true).unwrap();
+
+    assert!(existing);
+    assert_eq!(backend.contents(), contents);
+    assert!(backend.backup.borrow().is_none());
+}
+
+#[test]
+fn add_entry_reports_conflicting_user_entry() {
+    let contents = b"10.0.0.1 gosredirector.ea.com\n";
+    let backend = MemoryBackend::new(contents);
+
+    let result = HostEntryGuard::add_entry(&backend, &Redirect::defaults(), true);
+
+    assert!(matches!(result, Err(HostsError::ConflictingEntries(_))));
+    assert_eq!(backend.contents(), contents);
+}
+
+#[test]
+fn add_entry_reports_conflicting_running_owner() {
+    let contents = format!(
+        "{BLOCK_BEGIN}\n10.0.0.1 {HOST_KEY} # {OWNER_TAG} pid={} time=0\n{BLOCK_END}\n",
+        process::id()
+    );
+    let backend = MemoryBackend::new(contents.as_bytes());
+
+    let result = HostEntryGuard::add_entry(&backend, &Redirect::defaults(), true);
+
+    assert!(matches!(result, Err(HostsError::ConflictingEntries(_))));
+    assert_eq!(backend.contents(), contents.as_bytes());
+}
+
+#[test]
+fn add_entry_ignores_commented_entry() {
+    let backend = MemoryBackend::new(b"# 127.0.0.1 gosredirector.ea.com\n");
+
+    let existing = HostEntryGuard::add_entry(&backend, &Redirect::defaults(), false).unwrap();
+
+    assert!(!existing);
+    assert_eq!(
+        String::from_utf8(backend.contents()).unwrap(),
+        format!("# 127.0.0.1 gosredirector.ea.com\n{}", unowned_block("\n"))
+    );
+}
+
+#[test]
+fn add_entry_keeps_running_owner() {
+    let contents = format!(
+        "{BLOCK_BEGIN}\n{HOST_VALUE} {HOST_KEY} # {OWNER_TAG} pid={} time=0\n{BLOCK_END}\n",
+        process::id()
+    );
+    let backend = MemoryBackend::new(contents.as_bytes());
+
+    let existing = HostEntryGuard::add_entry(&backend, &Redirect::defaults(), true).unwrap();
+
+    assert!(existing);
+    assert_eq!(backend.contents(), contents.as_bytes());
+}
+
+#[test]
+fn add_entry_reclaims_orphaned_entry() {
+    let contents = format!(
+        "127.0.0.1 localhost\n{BLOCK_BEGIN}\n{HOST_VALUE} {HOST_KEY} # {OWNER_TAG} pid={DEAD_PID} time=0\n{BLOCK_END}\n"
+    );
+    let backend = MemoryBackend::new(contents.as_bytes());
+
+    let existing = HostEntryGuard::add_entry(&backend, &Redirect::defaults(), false).unwrap();
+
+    assert!(!existing);
+    assert_eq!(
+        String::from_utf8(backend.contents()).unwrap(),
+        format!("127.0.0.1 localhost\n{}", unowned_block("\n"))
+    );
+}
+
+#[test]
+fn add_entry_keeps_line_endings() {
+    let backend = MemoryBackend::new(b"127.0.0.1 localhost\r\n::1 localhost");
+
+    HostEntryGuard::add_entry(&backend, &Redirect::defaults(), false).unwrap();
+
+    assert_eq!(
+        String::from_utf8(backend.contents()).unwrap(),
+        format!(
+            "127.0.0.1 localhost\r\n::1 localhost\r\n{}",
+            unowned_block("\r\n")
+        )
+    );
+}
+
+#[test]
+fn add_entry_keeps_non_utf8_content() {
+    let contents = b"# caf\xe9  \n127.0.0.1 localhost\n";
+    let backend = MemoryBackend::new(contents);
+
+    HostEntryGuard::add_entry(&backend, &Redirect::defaults(), false).unwrap();
+
+    assert!(backend.contents().starts_with(contents));
+}
+
+#[test]
+fn add_entry_missing_file() {
+    let backend = MemoryBackend::default();
+
+    let result = HostEntryGuard::add_entry(&backend, &Redirect::defaults(), false);
+
+    assert!(matches!(result, Err(HostsError::FileMissing)));
+}
+
+#[test]
+fn add_entry_permission_denied() {
+    let backend = MemoryBackend {
+        read_only: true,
+        ..MemoryBackend::new(b"127.0.0.1 localhost\n")
+    };
+
+    let result = HostEntryGuard::add_entry(&backend, &Redirect::defaults(), false);
+
+    assert!(matches!(result, Err(HostsError::PermissionsError)));
+    assert_eq!(backend.contents(), b"127.0.0.1 localhost\n");
+}
+
+#[test]
+fn remove_entry_round_trip() {
+    let contents = b"127.0.0.1 localhost \r\n# comment\r\n::1 localhost\r\n";
+    let backend = MemoryBackend::new(contents);
+
+    HostEntryGuard::add_entry(&backend, &Redirect::defaults(), true).unwrap();
+    let removed = HostEntryGuard::remove_entry(&backend).unwrap();
+
+    assert_eq!(removed.len(), 1);
+    assert!(is_host_line(&removed[0]));
+    assert_eq!(backend.contents(), contents);
+}
+
+#[test]
+fn remove_entry_keeps_user_entry() {
+    let contents = format!(
+        "127.0.0.1 gosredirector.ea.com\n{BLOCK_BEGIN}\n{HOST_VALUE} {HOST_KEY}\n{BLOCK_END}\n"
+    );
+    let backend = MemoryBackend::new(contents.as_bytes());
+
+    let removed = HostEntryGuard::remove_entry(&backend).unwrap();
+
+    assert_eq!(removed, vec![format!("{HOST_VALUE} {HOST_KEY}")]);
+    assert_eq!(backend.contents(), b"127.0.0.1 gosredirector.ea.com\n");
+}
+
+#[test]
+fn remove_entry_without_block() {
+    let contents = b"127.0.0.1 gosredirector.ea.com\n";
+    let backend = MemoryBackend::new(contents);
+
+    let removed = HostEntryGuard::remove_entry(&backend).unwrap();
+
+    assert!(removed.is_empty());
+    assert_eq!(backend.contents(), contents);
+    assert!(backend.backup.borrow().is_none());
+}
+
+#[test]
+fn remove_entry_permission_denied() {
+    let contents = unowned_block("\n");
+    let backend = MemoryBackend {
+        read_only: true,
+        ..MemoryBackend::new(contents.as_bytes())
+    };
+
+    let result = HostEntryGuard::remove_entry(&backend);
+
+    assert!(matches!(result, Err(HostsError::PermissionsError)));
+}
+
+#[test]
+fn find_blocks_ignores_unmatched_markers() {
+    let lines = [
+        BLOCK_END,
+        BLOCK_BEGIN,
+        "127.0.0.1 localhost",
+        BLOCK_BEGIN,
+        "127.0.0.1 gosredirector.ea.com",
+        BLOCK_END,
+        BLOCK_BEGIN,
+    ];
+
+    assert_eq!(find_blocks(&lines), vec![3..=5]);
+}
+
+#[test]
+fn entry_owner_parse() {
+    let owner =
+        EntryOwner::parse("127.0.0.1 gosredirector.ea.com # pocket-relay-client pid=42 time=7")
+            .unwrap();
+    assert_eq!(owner.pid, 42);
+    assert_eq!(owner.timestamp, 7);
+
+    assert!(EntryOwner::parse("127.0.0.1 gosredirector.ea.com").is_none());
+    assert!(EntryOwner::parse("127.0.0.1 gosredirector.ea.com # other pid=42").is_none());
+    assert!(EntryOwner::parse("127.0.0.1 gosredirector.ea.com # pocket-relay-client").is_none());
+}
+
+#[test]
+fn file_backend_round_trip() {
+    let path = temp_dir().join(format!("pocket-relay-hosts-test-{}", process::id()));
+    let backup_path = crate::files::with_suffix(&path, ".pocket-relay.bak");
+    let contents = b"127.0.0.1 localhost\r\n";
+    write(&path, contents).unwrap();
+
+    let backend = FileBackend::new(&path);
+    HostEntryGuard::add_entry(&backend, &Redirect::defaults(), false).unwrap();
+    let applied = read(&path).unwrap();
+    HostEntryGuard::remove_entry(&backend).unwrap();
+    let removed = read(&path).unwrap();
+    let backup = read(&backup_path).unwrap();
+
+    let _ = remove_file(&path);
+    let _ = remove_file(&backup_path);
+
+    assert_eq!(
+        applied,
+        format!("127.0.0.1 localhost\r\n{}", unowned_block("\r\n")).as_bytes()
+    );
+    assert_eq!(removed, contents);
+    assert_eq!(backup, contents);
+}
+
+#[test]
+fn file_backend_missing_file() {
+    let backend = FileBackend::new(temp_dir().join("pocket-relay-hosts-test-missing"));
+
+    assert!(matches!(backend.read(), Err(HostsError::FileMissing)));
+}
+
+#[test]
+fn conflicting_line_detection() {
+    assert!(is_conflicting_line(
+        "10.0.0.5 gosredirector.ea.com",
+        &Redirect::defaults()
+    ));
+    assert!(is_conflicting_line(
+        "0.0.0.0 localhost gosredirector.ea.com # old server",
+        &Redirect::defaults()
+    ));
+    assert!(!is_conflicting_line(
+        "127.0.0.1 gosredirector.ea.com",
+        &Redirect::defaults()
+    ));
+    assert!(!is_conflicting_line(
+        "# 10.0.0.5 gosredirector.ea.com",
+        &Redirect::defaults()
+    ));
+    assert!(!is_conflicting_line(
+        "10.0.0.5 example.com",
+        &Redirect::defaults()
+    ));
+}
+
+#[test]
+fn disable_conflicts_round_trip() {
+    let contents = b"127.0.0.1 localhost\r\n10.0.0.5 gosredirector.ea.com # old\r\n";
+    let backend = MemoryBackend::new(contents);
+
+    let disabled = HostEntryGuard::disable_conflicts(&backend, &Redirect::defaults()).unwrap();
+    assert_eq!(disabled, vec!["10.0.0.5 gosredirector.ea.com # old"]);
+    assert!(find_conflicts(&backend, &Redirect::defaults())
+        .unwrap()
+        .is_empty());
+
+    // Entry is added once the conflicts are commented out
+    let existing = HostEntryGuard::add_entry(&backend, &Redirect::defaults(), false).unwrap();
+    assert!(!existing);
+
+    HostEntryGuard::remove_entry(&backend).unwrap();
+    let restored = HostEntryGuard::restore_conflicts(&backend).unwrap();
+
+    assert_eq!(restored, disabled);
+    assert_eq!(backend.contents(), contents);
+}
+
+#[test]
+fn disable_conflicts_tags_process() {
+    let backend = MemoryBackend::new(b"10.0.0.5 gosredirector.ea.com\n");
+
+    HostEntryGuard::disable_conflicts(&backend, &Redirect::defaults()).unwrap();
+
+    assert_eq!(
+        String::from_utf8(backend.contents()).unwrap(),
+        format!(
+            "{DISABLED_TAG} pid={}: 10.0.0.5 gosredirector.ea.com\n",
+            process::id()
+        )
+    );
+}
+
+#[test]
+fn restore_orphaned_conflicts() {
+    let contents = format!(
+        "{DISABLED_TAG} pid={DEAD_PID}: 10.0.0.5 gosredirector.ea.com\n\
+        {DISABLED_TAG}: 10.0.0.6 gosredirector.ea.com\n\
+        {DISABLED_TAG} pid={}: 10.0.0.7 gosredirector.ea.com\n",
+        process::id()
+    );
+    let backend = MemoryBackend::new(contents.as_bytes());
+
+    let restored = HostEntryGuard::restore_orphaned_conflicts(&backend).unwrap();
+
+    assert_eq!(
+        restored,
+        vec![
+            "10.0.0.5 gosredirector.ea.com",
+            "10.0.0.6 gosredirector.ea.com"
+        ]
+    );
+    assert_eq!(
+        String::from_utf8(backend.contents()).unwrap(),
+        format!(
+            "10.0.0.5 gosredirector.ea.com\n10.0.0.6 gosredirector.ea.com\n\
+            {DISABLED_TAG} pid={}: 10.0.0.7 gosredirector.ea.com\n",
+            process::id()
+        )
+    );
+}
+
+#[test]
+fn restore_conflicts_without_disabled() {
+    let contents = b"10.0.0.5 gosredirector.ea.com\n";
+    let backend = MemoryBackend::new(contents);
+
+    let restored = HostEntryGuard::restore_conflicts(&backend).unwrap();
+
+    assert!(restored.is_empty());
+    assert!(backend.backup.borrow().is_none());
+}
+
+#[test]
+fn add_entry_multiple_redirects() {
+    let redirects: Vec<Redirect> = serde_json::from_str(
+        r#"[
+            { "host": "gosredirector.ea.com", "address": "192.168.1.20" },
+            { "host": "example.ea.com", "address": "::1" }
+        ]"#,
+    )
+    .unwrap();
+    let backend = MemoryBackend::new(b"::1 example.ea.com\n");
+
+    let existing = HostEntryGuard::add_entry(&backend, &redirects, false).unwrap();
+
+    // Only the redirect without a user entry is added
+    assert!(!existing);
+    assert_eq!(
+        String::from_utf8(backend.contents()).unwrap(),
+        format!(
+            "::1 example.ea.com\n{BLOCK_BEGIN}\n192.168.1.20 gosredirector.ea.com\n{BLOCK_END}\n"
+        )
+    );
+    assert!(is_conflicting_line(
+        "127.0.0.1 gosredirector.ea.com",
+        &redirects
+    ));
+    assert!(!is_conflicting_line(
+        "192.168.1.20 gosredirector.ea.com",
+        &redirects
+    ));
+}
+
+#[test]
+fn redirect_validation() {
+    let parse = |value: &str| serde_json::from_str::<Redirect>(value);
+
+    assert!(parse(r#"{ "host": "gosredirector.ea.com", "address": "10.0.0.1" }"#).is_ok());
+    assert!(parse(r#"{ "host": "gosredirector.ea.com", "address": "localhost" }"#).is_err());
+    assert!(parse(r#"{ "host": "gosredirector.ea.com", "address": "10.0.0" }"#).is_err());
+    assert!(parse(r#"{ "host": "", "address": "10.0.0.1" }"#).is_err());
+    assert!(parse(r#"{ "host": "bad host", "address": "10.0.0.1" }"#).is_err());
+    assert!(parse(r#"{ "host": "bad#host", "address": "10.0.0.1" }"#).is_err());
+}
@@ -0,0 +1,196 @@
+//! Watches the hosts file while the client is running, other programs
+//! (i.e. VPN clients) may rewrite the hosts file removing the entries
+//! which are then applied again
+
+use super::{
+    find_host_entries, is_redirect_line, FileBackend, HostEntryGuard, HostsBackend, HostsError,
+    Redirect, ENTRY_ADDED, HELPER_CONNECTION,
+};
+use crate::ui::show_warning;
+use log::{debug, error, warn};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// Interval between checks for changes to the hosts file
+const HOSTS_WATCH_INTERVAL: Duration = Duration::from_secs(2);
+/// Time to wait for the hosts file to stop changing before checking
+/// the entries, other programs may not write the file atomically
+const HOSTS_SETTLE_DELAY: Duration = Duration::from_millis(500);
+
+/// Whether the hosts file is being watched, locked while the entries
+/// are applied again so the cleanup can't remove them at the same time
+static WATCHING: Mutex<bool> = Mutex::new(false);
+/// Whether the warning about the removed entries is currently shown
+static WARNING_SHOWN: AtomicBool = AtomicBool::new(false);
+
+/// Starts watching the hosts file for changes, the entries for the
+/// `redirects` are applied again if they are removed
+///
+/// ## Arguments
+/// * `redirects` - The redirects to keep applied
+pub fn watch_entries(redirects: Vec<Redirect>) {
+    match WATCHING.lock() {
+        Ok(mut watching) => *watching = true,
+        Err(_) => return,
+    }
+
+    let result = std::thread::Builder::new()
+        .name("hosts-watcher".to_string())
+        .spawn(move || watch(&redirects));
+
+    if let Err(err) = result {
+        error!("Failed to start hosts file watcher: {}", err);
+    }
+}
+
+/// Stops watching the hosts file, waits for the entries to finish
+/// being applied if they are being applied again
+pub fn stop_watching() {
+    if let Ok(mut watching) = WATCHING.lock() {
+        *watching = false;
+    }
+}
+
+/// Checks the hosts file for changes until watching is stopped
+///
+/// ## Arguments
+/// * `redirects` - The redirects to keep applied
+fn watch(redirects: &[Redirect]) {
+    let backend = FileBackend::system();
+    let mut last = backend.read().ok();
+
+    loop {
+        std::thread::sleep(HOSTS_WATCH_INTERVAL);
+
+        let bytes = backend.read().ok();
+        if bytes == last {
+            continue;
+        }
+
+        // Wait for the file to finish being written, empty files are
+        // likely still being written so are checked again later
+        std::thread::sleep(HOSTS_SETTLE_DELAY);
+        let settled = backend.read().ok();
+        if settled != bytes || settled.as_ref().is_some_and(Vec::is_empty) {
+            continue;
+        }
+
+        last = bytes;
+
+        let Ok(watching) = WATCHING.lock() else {
+            return;
+        };
+
+        if !*watching {
+            return;
+        }
+
+        debug!("Hosts file changed");
+
+        let removed: Vec<&Redirect> = match find_host_entries(&backend, redirects) {
+            Ok(entries) => redirects
+                .iter()
+                .filter(|redirect| !entries.iter().any(|line| is_redirect_line(line, redirect)))
+                .collect(),
+            Err(err) => {
+                debug!("Failed to check hosts file entries: {}", err);
+                continue;
+            }
+        };
+
+        if removed.is_empty() {
+            continue;
+        }
+
+        let hosts = removed
+            .iter()
+            .map(|redirect| redirect.host.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        warn!(
+            "Hosts entries for {} were removed, applying them again",
+            hosts
+        );
+
+        let (title, text) = match reapply(&backend, redirects) {
+            Ok(()) => (
+                "Hosts file redirect removed",
+                format!(
+                    "Another program removed the redirect for {hosts} from your hosts file, \
+                    the redirect has been added again.\n\nIf this keeps happening check for \
+                    programs that modify the hosts file (i.e. VPN clients)"
+                ),
+            ),
+            Err(err) => {
+                error!("Failed to apply host entries again: {}", err);
+                (
+                    "Failed to restore host modification",
+                    format!(
+                        "Another program removed the redirect for {hosts} from your hosts \
+                        file and it couldn't be added again:\n\n{}",
+                        err.message(redirects)
+                    ),
+                )
+            }
+        };
+
+        drop(watching);
+        warn_removed(title, text);
+    }
+}
+
+/// Applies the entries again, the hosts helper is used if the entries
+/// were applied by the helper. Otherwise the entries are only written
+/// directly when the client can already write the hosts file, privileges
+/// are never raised while the client is running
+///
+/// ## Arguments
+/// * `backend`   - The hosts file backend
+/// * `redirects` - The redirects to apply
+fn reapply(backend: &FileBackend, redirects: &[Redirect]) -> Result<(), HostsError> {
+    if let Ok(mut connection) = HELPER_CONNECTION.lock() {
+        if let Some(connection) = connection.as_mut() {
+            connection.apply(redirects, false)?;
+            return Ok(());
+        }
+    }
+
+    if !backend.is_writable() {
+        return Err(HostsError::PermissionsError);
+    }
+
+    if !HostEntryGuard::add_entry(backend, redirects, true)? {
+        ENTRY_ADDED.store(true, Ordering::SeqCst);
+    }
+
+    Ok(())
+}
+
+/// Shows the warning about the removed entries without blocking the
+/// watcher, the warning isn't shown again while its already shown
+///
+/// ## Arguments
+/// * `title` - The title of the warning
+/// * `text`  - The warning message
+fn warn_removed(title: &'static str, text: String) {
+    if WARNING_SHOWN.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let result = std::thread::Builder::new()
+        .name("hosts-warning".to_string())
+        .spawn(move || {
+            show_warning(title, &text);
+            WARNING_SHOWN.store(false, Ordering::SeqCst);
+        });
+
+    if result.is_err() {
+        WARNING_SHOWN.store(false, Ordering::SeqCst);
+    }
+}
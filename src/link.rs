@@ -0,0 +1,185 @@
+//! Shareable connection links in the format:
+//! `pocketrelay://connect?url=<CONNECTION_URL>&name=<NAME>`
+
+use std::{fmt::Display, str::FromStr};
+use thiserror::Error;
+use url::Url;
+
+/// Scheme used by connection links
+pub const LINK_SCHEME: &str = "pocketrelay";
+/// Action for connection links
+const CONNECT_ACTION: &str = "connect";
+
+/// Link containing the details for connecting to a server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionLink {
+    /// The connection URL of the server
+    pub url: String,
+    /// Optional display name for the server
+    pub name: Option<String>,
+    /// Whether the client should connect immediately
+    pub connect: bool,
+}
+
+/// Errors that could occur while parsing a connection link
+#[derive(Debug, Error)]
+pub enum LinkError {
+    /// Link wasn't a valid URI
+    #[error("Invalid connection link: {0}")]
+    Invalid(#[from] url::ParseError),
+    /// Link used the wrong scheme
+    #[error("Connection links must start with {LINK_SCHEME}://")]
+    Scheme,
+    /// Link used an unknown action
+    #[error("Unknown connection link action: {0}")]
+    Action(String),
+    /// Link was missing the connection URL
+    #[error("Connection link is missing the connection URL")]
+    MissingUrl,
+}
+
+impl ConnectionLink {
+    /// Creates a new link for the provided connection URL
+    ///
+    /// ## Arguments
+    /// * `url`  - The connection URL
+    /// * `name` - Optional display name for the server
+    #[cfg(any(feature = "iced", feature = "native"))]
+    pub fn new(url: String, name: Option<String>) -> Self {
+        Self {
+            url,
+            name,
+            connect: false,
+        }
+    }
+
+    /// Checks whether the provided `value` looks like a connection link
+    /// rather than a connection URL
+    ///
+    /// ## Arguments
+    /// * `value` - The value to check
+    pub fn is_link(value: &str) -> bool {
+        value
+            .trim()
+            .get(..LINK_SCHEME.len() + 1)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(&format!("{LINK_SCHEME}:")))
+    }
+}
+
+impl FromStr for ConnectionLink {
+    type Err = LinkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let link = Url::parse(s.trim())?;
+
+        if link.scheme() != LINK_SCHEME {
+            return Err(LinkError::Scheme);
+        }
+
+        let action = link.host_str().unwrap_or_default();
+        if action != CONNECT_ACTION {
+            return Err(LinkError::Action(action.to_string()));
+        }
+
+        let mut url = None;
+        let mut name = None;
+        let mut connect = false;
+
+        for (key, value) in link.query_pairs() {
+            match key.as_ref() {
+                "url" => url = Some(value.trim().to_string()),
+                "name" => name = Some(value.trim().to_string()),
+                "connect" => connect = matches!(value.as_ref(), "1" | "true"),
+                _ => {}
+            }
+        }
+
+        let url = url
+            .filter(|value| !value.is_empty())
+            .ok_or(LinkError::MissingUrl)?;
+        let name = name.filter(|value| !value.is_empty());
+
+        Ok(Self { url, name, connect })
+    }
+}
+
+impl Display for ConnectionLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut link = Url::parse(&format!("{LINK_SCHEME}://{CONNECT_ACTION}"))
+            .expect("Connection link base was invalid");
+
+        {
+            let mut query = link.query_pairs_mut();
+            query.append_pair("url", &self.url);
+
+            if let Some(name) = &self.name {
+                query.append_pair("name", name);
+            }
+
+            if self.connect {
+                query.append_pair("connect", "1");
+            }
+        }
+
+        link.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_link() {
+        let link = ConnectionLink {
+            url: "https://example.com:8080/relay".to_string(),
+            name: Some("My Server & Friends".to_string()),
+            connect: true,
+        };
+
+        let text = link.to_string();
+        assert!(text.starts_with("pocketrelay://connect?url="));
+        assert_eq!(text.parse::<ConnectionLink>().unwrap(), link);
+    }
+
+    #[test]
+    fn parses_link_without_name() {
+        let link: ConnectionLink = " pocketrelay://connect?url=127.0.0.1 ".parse().unwrap();
+
+        assert_eq!(link.url, "127.0.0.1");
+        assert_eq!(link.name, None);
+        assert!(!link.connect);
+    }
+
+    #[test]
+    fn rejects_wrong_scheme() {
+        let result = "https://connect?url=127.0.0.1".parse::<ConnectionLink>();
+
+        assert!(matches!(result, Err(LinkError::Scheme)));
+    }
+
+    #[test]
+    fn rejects_unknown_host() {
+        let result = "pocketrelay://join?url=127.0.0.1".parse::<ConnectionLink>();
+
+        assert!(matches!(result, Err(LinkError::Action(action)) if action == "join"));
+    }
+
+    #[test]
+    fn rejects_missing_url() {
+        let result = "pocketrelay://connect?name=Server".parse::<ConnectionLink>();
+        assert!(matches!(result, Err(LinkError::MissingUrl)));
+
+        let result = "pocketrelay://connect?url=%20&name=Server".parse::<ConnectionLink>();
+        assert!(matches!(result, Err(LinkError::MissingUrl)));
+    }
+
+    #[test]
+    fn detects_links() {
+        assert!(ConnectionLink::is_link(
+            "PocketRelay://connect?url=127.0.0.1"
+        ));
+        assert!(!ConnectionLink::is_link("http://127.0.0.1"));
+        assert!(!ConnectionLink::is_link("127.0.0.1"));
+    }
+}
@@ -1,60 +1,256 @@
 #![cfg_attr(
-    all(target_os = "windows", not(debug_assertions),),
+    all(
+        target_os = "windows",
+        not(debug_assertions),
+        any(feature = "iced", feature = "native")
+    ),
     windows_subsystem = "windows"
 )]
 #![warn(unused_crate_dependencies)]
 
 use crate::ui::show_error;
-use config::read_config_file;
+use args::{parse_args, USAGE};
+use config::{
+    init_config_path, migrate_portable_config, read_config_file, write_config_file, ClientConfig,
+};
 use core::{api::create_http_client, api::read_client_identity, reqwest};
-use hosts::HostEntryGuard;
+use doctor::run_doctor_command;
+use hosts::{run_hosts_command, run_hosts_helper, watch_entries, HostEntryGuard};
 use log::error;
 use pocket_relay_client_shared as core;
-use std::path::Path;
-use ui::show_confirm;
+use policy::load_policy;
+use settings::{EnvSettings, RedirectMode, Settings, DEFAULT_LOG_LEVEL};
+use std::process::{exit, ExitCode};
+use ui::{is_headless, set_headless, show_confirm};
 
+mod args;
 mod config;
+mod control;
+mod dns;
+mod doctor;
+mod files;
+mod history;
 mod hosts;
+mod link;
+#[cfg(target_family = "windows")]
+mod pipe;
+mod policy;
+mod privileges;
 mod servers;
+mod settings;
+mod shutdown;
 mod ui;
 mod update;
 
+#[cfg(not(any(feature = "iced", feature = "native", feature = "cli")))]
+compile_error!("At least one of the iced, native or cli features must be enabled");
+
 /// Application crate version string
 pub const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
 
-fn main() {
-    // Initialize logging
+fn main() -> ExitCode {
+    // Initialize logging before loading the config files so their errors are
+    // logged, the level is changed once the settings are resolved and when
+    // the config is reloaded
     env_logger::builder()
-        .filter_module("pocket_relay_client", log::LevelFilter::Debug)
+        .filter_module("pocket_relay_client", log::LevelFilter::Trace)
         .init();
+    log::set_max_level(DEFAULT_LOG_LEVEL);
+
+    // Parse the command line arguments
+    let args = match parse_args() {
+        Ok(value) => value,
+        Err(err) => exit_with_error("Invalid arguments", &format!("{err}\n\n{USAGE}")),
+    };
+
+    if args.help {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+
+    if args.version {
+        println!("{APP_VERSION}");
+        return ExitCode::SUCCESS;
+    }
+
+    // Messages are shown in the terminal when running headless
+    set_headless(args.headless);
 
-    // Attempt to apply the hosts file modification guard
-    let _host_guard: Option<HostEntryGuard> = HostEntryGuard::apply();
+    // Read the environment variable settings
+    let mut env = match EnvSettings::from_env() {
+        Ok(value) => value,
+        Err(err) => exit_with_error("Invalid environment variables", &err.to_string()),
+    };
+
+    if env.headless {
+        set_headless(true);
+    }
+
+    if is_headless() && !cfg!(feature = "cli") {
+        exit_with_error(
+            "Headless mode unavailable",
+            "This client was built without the cli feature",
+        );
+    }
+
+    // Read and write the config files as the user that invoked sudo
+    if args.hosts.is_none() && !args.hosts_helper {
+        if let Err(err) = privileges::switch_to_sudo_user() {
+            exit_with_error("Failed to drop root privileges", &err.to_string());
+        }
+    }
+
+    // Determine the config file location
+    let explicit_config_path = args.config_path.clone().or(env.config_path.take());
+    let use_default_config_path = explicit_config_path.is_none();
+    init_config_path(explicit_config_path, args.portable || env.portable);
+
+    // Move the config file from older versions to the new location
+    if use_default_config_path {
+        migrate_portable_config();
+    }
 
     // Load the config file
-    let config: Option<config::ClientConfig> = read_config_file();
+    let mut config: Option<ClientConfig> = read_config_file();
+
+    // Load the policy file
+    let policy = match load_policy() {
+        Ok(value) => value,
+        Err(err) => exit_with_error("Invalid policy file", &err.to_string()),
+    };
+
+    // Save the profile from the connection link unless the policy locks
+    // the connection URL or hides remembering connections
+    if let Some(link) = &args.link {
+        if policy.connection_url.is_none() && !policy.hide_remember {
+            let config = config.get_or_insert_with(ClientConfig::default);
+            config.add_profile(&link.url, link.name.clone());
+            write_config_file(config);
+        }
+    }
+
+    // Resolve the settings from the config, environment, arguments and policy
+    let settings = Settings::resolve(config.as_ref(), env, &args, policy);
+    log::set_max_level(settings.log_level);
+
+    // Run hosts file commands without starting the client
+    if let Some(command) = args.hosts {
+        return run_hosts_command(command, &settings.redirects);
+    }
+
+    // Run the hosts helper without starting the client
+    if args.hosts_helper {
+        return run_hosts_helper(&settings.redirects, args.exit_when_idle);
+    }
+
+    // Run the diagnostics as the user that invoked sudo without starting the client
+    if args.doctor {
+        if let Err(err) = privileges::drop_privileges(None, || ()) {
+            exit_with_error("Failed to drop root privileges", &err.to_string());
+        }
+
+        return run_doctor_command(config.as_ref(), &settings);
+    }
+
+    // Bind the DNS server while root is still held, the hosts file is
+    // modified by a helper forked before dropping root
+    let dns_socket = match privileges::drop_privileges(
+        (settings.redirect_mode == RedirectMode::Hosts).then_some(settings.redirects.as_slice()),
+        || (settings.redirect_mode == RedirectMode::Dns).then(|| dns::bind(settings.dns_address)),
+    ) {
+        Ok(value) => value,
+        Err(err) => exit_with_error("Failed to drop root privileges", &err.to_string()),
+    };
+
+    // Remove the hosts entry when stopped by a signal or panic, installed
+    // before applying so an interrupted apply is still cleaned up. The
+    // handler thread is started after the hosts helper is forked
+    shutdown::install_handlers();
+
+    // Attempt to apply the hosts file modification guard or start the DNS server
+    let _host_guard: Option<HostEntryGuard> = match settings.redirect_mode {
+        RedirectMode::Hosts => {
+            let guard = HostEntryGuard::apply(&settings.redirects);
+
+            // Apply the entries again if another program removes them
+            if guard.is_some() {
+                watch_entries(settings.redirects.clone());
+            }
+
+            guard
+        }
+        RedirectMode::Dns => {
+            if let Some(socket) = dns_socket {
+                dns::start(&settings, socket);
+            }
+            None
+        }
+    };
 
     // Load the client identity
-    let identity: Option<reqwest::Identity> = load_identity();
+    let identity: Option<reqwest::Identity> = load_identity(&settings);
 
     // Create the internal HTTP client
     let client: reqwest::Client =
         create_http_client(identity).expect("Failed to create HTTP client");
 
     // Initialize the UI
-    ui::init(config, client);
+    ui::init(config, settings, client)
+}
+
+/// Displays an error message to the user and exits the process
+///
+/// ## Arguments
+/// * `title` - The title of the error
+/// * `text`  - The error message
+fn exit_with_error(title: &str, text: &str) -> ! {
+    eprintln!("{title}: {text}");
+
+    // Headless messages are already shown in the terminal
+    if !is_headless() {
+        show_error(title, text);
+    }
+
+    exit(2);
 }
 
-/// Attempts to load an identity file if one is present
-fn load_identity() -> Option<reqwest::Identity> {
+/// Attempts to load an identity file if one is present, identity files
+/// that weren't explicitly provided require confirmation from the user
+///
+/// ## Arguments
+/// * `settings` - The settings containing the identity path
+fn load_identity(settings: &Settings) -> Option<reqwest::Identity> {
     // Load the client identity
-    let identity_file = Path::new("pocket-relay-identity.p12");
+    let identity_file = settings.identity_path.as_path();
 
-    // Handle no identity or user declining identity
-    if !identity_file.exists() || !show_confirm(
-        "Found client identity",
-        "Detected client identity pocket-relay-identity.p12, would you like to use this identity?",
-    ) {
+    if !identity_file.exists() {
+        // Explicitly provided identities should exist
+        if settings.identity_explicit {
+            error!(
+                "Client identity file is missing: {}",
+                identity_file.display()
+            );
+            show_error(
+                "Failed to set client identity",
+                &format!(
+                    "Client identity file {} is missing",
+                    identity_file.display()
+                ),
+            );
+        }
+        return None;
+    }
+
+    // Handle user declining identity
+    if !settings.identity_explicit
+        && !show_confirm(
+            "Found client identity",
+            &format!(
+                "Detected client identity {}, would you like to use this identity?",
+                identity_file.display()
+            ),
+        )
+    {
         return None;
     }
 
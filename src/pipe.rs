@@ -0,0 +1,151 @@
+//! Named pipes used on Windows by the control API and the hosts helper, the
+//! pipes are created with an access control list so only the allowed users
+//! can connect and other users can't create their own instances of the pipe
+
+use std::{
+    ffi::{c_void, OsStr},
+    io,
+    iter::once,
+    os::windows::ffi::OsStrExt,
+    ptr::null_mut,
+};
+use tokio::net::windows::named_pipe::{NamedPipeServer, ServerOptions};
+use windows_sys::Win32::{
+    Foundation::{CloseHandle, HANDLE},
+    Security::{
+        Authorization::{
+            ConvertSidToStringSidW, ConvertStringSecurityDescriptorToSecurityDescriptorW,
+            SDDL_REVISION_1,
+        },
+        GetTokenInformation, TokenUser, PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES, TOKEN_QUERY,
+        TOKEN_USER,
+    },
+    System::{
+        Memory::LocalFree,
+        Threading::{GetCurrentProcess, OpenProcessToken},
+    },
+};
+
+/// Security descriptor applied to a named pipe
+pub struct PipeSecurity {
+    /// The security descriptor created from the SDDL string
+    descriptor: PSECURITY_DESCRIPTOR,
+}
+
+// The descriptor is only read after it is created
+unsafe impl Send for PipeSecurity {}
+unsafe impl Sync for PipeSecurity {}
+
+impl PipeSecurity {
+    /// Creates the security descriptor from the `sddl` string
+    ///
+    /// ## Arguments
+    /// * `sddl` - The security descriptor definition
+    pub fn new(sddl: &str) -> io::Result<Self> {
+        let sddl = to_wide(OsStr::new(sddl));
+        let mut descriptor: PSECURITY_DESCRIPTOR = null_mut();
+
+        let result = unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                sddl.as_ptr(),
+                SDDL_REVISION_1,
+                &mut descriptor,
+                null_mut(),
+            )
+        };
+
+        if result == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { descriptor })
+    }
+
+    /// Creates the security descriptor allowing only SYSTEM and the current
+    /// user to access the pipe
+    pub fn current_user() -> io::Result<Self> {
+        let sid = current_user_sid()?;
+        Self::new(&format!("D:P(A;;GA;;;SY)(A;;GA;;;{sid})"))
+    }
+
+    /// Creates an instance of the pipe `name`, creating the first instance
+    /// fails if another process already created the pipe
+    ///
+    /// ## Arguments
+    /// * `name`  - The name of the pipe
+    /// * `first` - Whether this is the first instance of the pipe
+    pub fn create_pipe(&self, name: &str, first: bool) -> io::Result<NamedPipeServer> {
+        let mut attributes = SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: self.descriptor,
+            bInheritHandle: 0,
+        };
+
+        unsafe {
+            ServerOptions::new()
+                .first_pipe_instance(first)
+                .reject_remote_clients(true)
+                .create_with_security_attributes_raw(
+                    name,
+                    &mut attributes as *mut SECURITY_ATTRIBUTES as *mut c_void,
+                )
+        }
+    }
+}
+
+impl Drop for PipeSecurity {
+    fn drop(&mut self) {
+        unsafe { LocalFree(self.descriptor as _) };
+    }
+}
+
+/// Provides the security identifier of the user running the process
+pub fn current_user_sid() -> io::Result<String> {
+    let mut token: HANDLE = 0;
+    if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // The first call provides the required buffer length
+    let mut length = 0;
+    unsafe { GetTokenInformation(token, TokenUser, null_mut(), 0, &mut length) };
+
+    // Buffer of u64 so the buffer is aligned for the token user
+    let mut buffer = vec![0u64; (length as usize).div_ceil(8)];
+    let result = unsafe {
+        GetTokenInformation(
+            token,
+            TokenUser,
+            buffer.as_mut_ptr() as *mut c_void,
+            length,
+            &mut length,
+        )
+    };
+    unsafe { CloseHandle(token) };
+
+    if result == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let user = unsafe { &*(buffer.as_ptr() as *const TOKEN_USER) };
+    let mut sid = null_mut();
+    if unsafe { ConvertSidToStringSidW(user.User.Sid, &mut sid) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let value = unsafe {
+        let length = (0..).take_while(|index| *sid.add(*index) != 0).count();
+        String::from_utf16_lossy(std::slice::from_raw_parts(sid, length))
+    };
+    unsafe { LocalFree(sid as _) };
+
+    Ok(value)
+}
+
+/// Converts the `value` to a null terminated wide string
+///
+/// ## Arguments
+/// * `value` - The value to convert
+pub fn to_wide(value: &OsStr) -> Vec<u16> {
+    value.encode_wide().chain(once(0)).collect()
+}
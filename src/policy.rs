@@ -0,0 +1,162 @@
+//! Optional read-only policy file used to lock settings when the client
+//! is distributed preconfigured (i.e. LAN events)
+
+use crate::settings::TunnelMode;
+use log::debug;
+use serde::Deserialize;
+use std::{
+    env::current_exe,
+    io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+use thiserror::Error;
+
+/// Name of the policy file
+pub const POLICY_FILE_NAME: &str = "pocket-relay-policy.json";
+/// System wide directory that can contain the policy file
+#[cfg(target_family = "unix")]
+pub const SYSTEM_POLICY_DIR: &str = "/etc/pocket-relay";
+
+/// Loaded policy
+static POLICY: OnceLock<Policy> = OnceLock::new();
+
+/// Structure of the policy file, fields that aren't specified
+/// are not locked
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Connection URL the client must use
+    #[serde(default)]
+    pub connection_url: Option<String>,
+    /// Whether updating the client is disabled
+    #[serde(default)]
+    pub disable_updates: bool,
+    /// Whether the "Save connection URL" option is hidden
+    #[serde(default)]
+    pub hide_remember: bool,
+    /// Tunnel mode the client must use
+    #[serde(default)]
+    pub tunnel: Option<TunnelMode>,
+}
+
+/// Errors that could occur while loading the policy file
+#[derive(Debug, Error)]
+pub enum PolicyError {
+    /// Failed to read the policy file
+    #[error("Failed to read policy file {0}: {1}")]
+    Read(PathBuf, io::Error),
+    /// Policy file was invalid
+    #[error("Invalid policy file {0}: {1}")]
+    Parse(PathBuf, serde_json::Error),
+}
+
+impl Policy {
+    /// Merges this policy with a lower priority `other` policy, the locks
+    /// from both policies apply and the values from this policy are used
+    /// when both policies provide a value
+    ///
+    /// ## Arguments
+    /// * `other` - The lower priority policy
+    fn merge(self, other: Policy) -> Policy {
+        Policy {
+            connection_url: self.connection_url.or(other.connection_url),
+            disable_updates: self.disable_updates || other.disable_updates,
+            hide_remember: self.hide_remember || other.hide_remember,
+            tunnel: self.tunnel.or(other.tunnel),
+        }
+    }
+}
+
+/// Provides the paths the policy file can be loaded from in order
+/// of priority, the system wide policy file can only be changed by
+/// admins so it takes priority over the file next to the executable
+fn policy_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+
+    // System wide policy file
+    #[cfg(target_family = "unix")]
+    paths.push(Path::new(SYSTEM_POLICY_DIR).join(POLICY_FILE_NAME));
+    #[cfg(target_family = "windows")]
+    if let Some(program_data) = std::env::var_os("ProgramData") {
+        paths.push(
+            PathBuf::from(program_data)
+                .join("pocket-relay")
+                .join(POLICY_FILE_NAME),
+        );
+    }
+
+    // Policy file next to the executable
+    if let Some(parent) = current_exe().ok().as_deref().and_then(Path::parent) {
+        paths.push(parent.join(POLICY_FILE_NAME));
+    }
+
+    paths
+}
+
+/// Loads the policy from all the policy files that exist merging them
+/// in order of priority, the default policy is used if there are no
+/// policy files
+pub fn load_policy() -> Result<&'static Policy, PolicyError> {
+    let mut policy = Policy::default();
+
+    for path in policy_paths() {
+        if !path.exists() {
+            continue;
+        }
+
+        debug!("Loading policy file {}", path.display());
+
+        let bytes = std::fs::read(&path).map_err(|err| PolicyError::Read(path.clone(), err))?;
+        let value: Policy =
+            serde_json::from_slice(&bytes).map_err(|err| PolicyError::Parse(path, err))?;
+
+        policy = policy.merge(value);
+    }
+
+    Ok(POLICY.get_or_init(|| policy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_prefers_higher_priority_values() {
+        let system = Policy {
+            connection_url: Some("http://192.168.0.10".to_string()),
+            disable_updates: true,
+            ..Policy::default()
+        };
+        let local = Policy {
+            connection_url: Some("http://example.com".to_string()),
+            hide_remember: true,
+            tunnel: Some(TunnelMode::Http),
+            ..Policy::default()
+        };
+
+        let policy = system.merge(local);
+
+        assert_eq!(
+            policy.connection_url.as_deref(),
+            Some("http://192.168.0.10")
+        );
+        assert!(policy.disable_updates);
+        assert!(policy.hide_remember);
+        assert_eq!(policy.tunnel, Some(TunnelMode::Http));
+    }
+
+    #[test]
+    fn merge_keeps_higher_priority_locks() {
+        let system = Policy {
+            disable_updates: true,
+            hide_remember: true,
+            ..Policy::default()
+        };
+
+        let policy = system.merge(Policy::default());
+
+        assert!(policy.disable_updates);
+        assert!(policy.hide_remember);
+    }
+}
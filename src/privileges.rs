@@ -0,0 +1,199 @@
+//! Dropping root privileges when the client was started using sudo, the
+//! client runs as the user that invoked sudo. A child process forked before
+//! the privileges are dropped keeps root and modifies the hosts file on
+//! behalf of the client, the client itself can't regain root
+
+use crate::hosts::Redirect;
+use std::io;
+
+/// Switches the effective user and group IDs to the user that invoked sudo
+/// so the config files are read and written as that user, the `HOME`
+/// variable is changed to the home directory of the user so their config
+/// file is used. Root is kept as the saved ID until [`drop_privileges`]
+/// is called, both are only used while the client is starting
+#[cfg(target_family = "unix")]
+pub fn switch_to_sudo_user() -> io::Result<()> {
+    use self::unix::{lookup_user, SudoUser, SUDO_USER};
+    use std::{env, ffi::CString};
+
+    /// Parses the ID from the environment variable `key`
+    fn env_id(key: &str) -> Option<u32> {
+        env::var(key).ok().and_then(|value| value.parse().ok())
+    }
+
+    // Only processes started by sudo are switched
+    if unsafe { libc::geteuid() } != 0 {
+        return Ok(());
+    }
+
+    let (Some(uid), Some(gid)) = (env_id("SUDO_UID"), env_id("SUDO_GID")) else {
+        return Ok(());
+    };
+
+    // Started using sudo from root
+    if uid == 0 {
+        return Ok(());
+    }
+
+    let (name, home) = lookup_user(uid).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Unknown sudo user ID {uid}"),
+        )
+    })?;
+
+    let name = CString::new(name).map_err(io::Error::from)?;
+    if unsafe { libc::initgroups(name.as_ptr(), gid as _) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if unsafe { libc::setegid(gid) } != 0 || unsafe { libc::seteuid(uid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    env::set_var("HOME", home);
+    let _ = SUDO_USER.set(SudoUser { uid, gid });
+
+    Ok(())
+}
+
+/// Switches to the user that invoked sudo, not applicable on windows
+#[cfg(target_family = "windows")]
+pub fn switch_to_sudo_user() -> io::Result<()> {
+    Ok(())
+}
+
+/// Permanently drops root privileges to the user that invoked sudo when the
+/// client was switched to that user by [`switch_to_sudo_user`]. The
+/// `as_root` function is called first while root is still held (i.e. to
+/// bind privileged ports) and its result is provided.
+///
+/// When `redirects` are provided a hosts helper that keeps root is forked
+/// before dropping, it only applies the provided `redirects` for the client
+/// and exits once the client exits
+///
+/// ## Arguments
+/// * `redirects` - The redirects for the forked hosts helper
+/// * `as_root`   - Function to call before the privileges are dropped
+#[cfg(target_family = "unix")]
+pub fn drop_privileges<T>(
+    redirects: Option<&[Redirect]>,
+    as_root: impl FnOnce() -> T,
+) -> io::Result<T> {
+    use self::unix::SUDO_USER;
+    use crate::hosts::{run_private_helper, set_helper_stream};
+    use std::os::unix::net::UnixStream;
+
+    let Some(user) = SUDO_USER.get() else {
+        return Ok(as_root());
+    };
+
+    // Root is regained from the saved ID to fork the helper, no other
+    // threads have been started yet
+    if unsafe { libc::seteuid(0) } != 0 || unsafe { libc::setegid(0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let value = as_root();
+
+    if let Some(redirects) = redirects {
+        let (stream, helper_stream) = UnixStream::pair()?;
+
+        match unsafe { libc::fork() } {
+            -1 => return Err(io::Error::last_os_error()),
+            0 => {
+                drop(stream);
+                run_private_helper(helper_stream, redirects.to_vec())
+            }
+            _ => drop(helper_stream),
+        }
+
+        set_helper_stream(stream);
+    }
+
+    // Changes the real, effective and saved IDs as the process is root
+    if unsafe { libc::setgid(user.gid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if unsafe { libc::setuid(user.uid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // Ensure root can't be regained
+    if unsafe { libc::setuid(0) } == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "Root privileges could be regained after dropping them",
+        ));
+    }
+
+    Ok(value)
+}
+
+/// Drops root privileges to the user that invoked sudo, not applicable
+/// on windows so `as_root` is called and its result is provided
+///
+/// ## Arguments
+/// * `redirects` - The redirects for the forked hosts helper
+/// * `as_root`   - Function to call before the privileges are dropped
+#[cfg(target_family = "windows")]
+pub fn drop_privileges<T>(
+    _redirects: Option<&[Redirect]>,
+    as_root: impl FnOnce() -> T,
+) -> io::Result<T> {
+    Ok(as_root())
+}
+
+#[cfg(target_family = "unix")]
+mod unix {
+    use std::{
+        ffi::{CStr, OsStr},
+        os::unix::ffi::OsStrExt,
+        path::PathBuf,
+        sync::OnceLock,
+    };
+
+    /// User that invoked sudo when the client was switched to that user
+    pub static SUDO_USER: OnceLock<SudoUser> = OnceLock::new();
+
+    /// User and group IDs of the user that invoked sudo
+    pub struct SudoUser {
+        /// The user ID
+        pub uid: libc::uid_t,
+        /// The primary group ID
+        pub gid: libc::gid_t,
+    }
+
+    /// Looks up the name and home directory of the user with the provided `uid`
+    ///
+    /// ## Arguments
+    /// * `uid` - The user ID
+    pub fn lookup_user(uid: libc::uid_t) -> Option<(Vec<u8>, PathBuf)> {
+        let mut buffer = vec![0 as libc::c_char; 16384];
+        let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result: *mut libc::passwd = std::ptr::null_mut();
+
+        let status = unsafe {
+            libc::getpwuid_r(
+                uid,
+                &mut passwd,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        };
+
+        if status != 0 || result.is_null() {
+            return None;
+        }
+
+        let name = unsafe { CStr::from_ptr(passwd.pw_name) }
+            .to_bytes()
+            .to_vec();
+        let home = unsafe { CStr::from_ptr(passwd.pw_dir) }.to_bytes();
+        let home = PathBuf::from(OsStr::from_bytes(home));
+
+        Some((name, home))
+    }
+}
@@ -1,49 +1,285 @@
 use crate::{
     core::{ctx::ClientContext, servers::*},
+    settings::TunnelMode,
     ui::show_error,
 };
-use log::error;
-use std::{future::Future, sync::Arc};
+use log::{debug, error};
+use serde::Serialize;
+use std::{
+    fmt::{Display, Write},
+    future::Future,
+    io,
+    net::{Ipv4Addr, TcpListener, UdpSocket},
+    sync::{Arc, Mutex},
+};
+
+/// Transport protocol used by a local server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    /// TCP stream socket
+    Tcp,
+    /// UDP datagram socket
+    Udp,
+}
+
+impl Display for Protocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Protocol::Tcp => f.write_str("TCP"),
+            Protocol::Udp => f.write_str("UDP"),
+        }
+    }
+}
+
+/// Details about a local server and the port it binds to
+#[derive(Debug)]
+pub struct LocalServer {
+    /// Name of the server
+    pub name: &'static str,
+    /// Protocol the server uses
+    pub protocol: Protocol,
+    /// Port the server binds to
+    pub port: u16,
+}
+
+/// The local servers and their ports, the ports and localhost bind address
+/// are fixed by the shared backend and the game expects these ports
+pub const LOCAL_SERVERS: [LocalServer; 6] = [
+    LocalServer {
+        name: "redirector",
+        protocol: Protocol::Tcp,
+        port: REDIRECTOR_PORT,
+    },
+    LocalServer {
+        name: "blaze",
+        protocol: Protocol::Tcp,
+        port: BLAZE_PORT,
+    },
+    LocalServer {
+        name: "http",
+        protocol: Protocol::Tcp,
+        port: HTTP_PORT,
+    },
+    LocalServer {
+        name: "qos",
+        protocol: Protocol::Udp,
+        port: QOS_PORT,
+    },
+    LocalServer {
+        name: "tunnel",
+        protocol: Protocol::Udp,
+        port: TUNNEL_HOST_PORT,
+    },
+    LocalServer {
+        name: "telemetry",
+        protocol: Protocol::Tcp,
+        port: TELEMETRY_PORT,
+    },
+];
+
+/// State of a local server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerState {
+    /// Server isn't running
+    Stopped,
+    /// Server is running
+    Running,
+    /// Server wasn't started because its port was unavailable
+    Unavailable,
+    /// Server wasn't started because its disabled
+    Disabled,
+    /// Server stopped with an error
+    Failed,
+}
+
+/// Current state of each of the [`LOCAL_SERVERS`]
+static SERVER_STATES: Mutex<[ServerState; LOCAL_SERVERS.len()]> =
+    Mutex::new([ServerState::Stopped; LOCAL_SERVERS.len()]);
+
+/// Sets the state of the local server with the provided `name`
+///
+/// ## Arguments
+/// * `name`  - The name of the server
+/// * `state` - The new server state
+fn set_server_state(name: &str, state: ServerState) {
+    let Some(index) = LOCAL_SERVERS.iter().position(|server| server.name == name) else {
+        return;
+    };
+
+    if let Ok(mut states) = SERVER_STATES.lock() {
+        states[index] = state;
+    }
+}
+
+/// Provides the current state of each of the local servers
+pub fn server_states() -> Vec<(&'static LocalServer, ServerState)> {
+    let states = match SERVER_STATES.lock() {
+        Ok(value) => *value,
+        Err(_) => [ServerState::Stopped; LOCAL_SERVERS.len()],
+    };
+
+    LOCAL_SERVERS.iter().zip(states).collect()
+}
+
+/// Stops all the running servers
+pub fn stop_all_servers() {
+    stop_server_tasks();
+
+    if let Ok(mut states) = SERVER_STATES.lock() {
+        *states = [ServerState::Stopped; LOCAL_SERVERS.len()];
+    }
+}
+
+/// Checks whether the port for the provided local `server` is available
+/// by briefly binding to it
+///
+/// ## Arguments
+/// * `server` - The server to check
+pub fn check_server_port(server: &LocalServer) -> io::Result<()> {
+    let addr = (Ipv4Addr::LOCALHOST, server.port);
+    match server.protocol {
+        Protocol::Tcp => TcpListener::bind(addr).map(drop),
+        Protocol::Udp => UdpSocket::bind(addr).map(drop),
+    }
+}
+
+/// Checks the ports of all the local servers, providing the servers
+/// whose ports are unavailable along with the bind error
+pub fn check_server_ports() -> Vec<(&'static LocalServer, io::Error)> {
+    LOCAL_SERVERS
+        .iter()
+        .filter_map(|server| check_server_port(server).err().map(|err| (server, err)))
+        .collect()
+}
+
+/// Creates a message describing the `unavailable` server ports
+///
+/// ## Arguments
+/// * `unavailable` - The servers with unavailable ports
+pub fn server_ports_message(unavailable: &[(&'static LocalServer, io::Error)]) -> String {
+    let mut message = String::new();
+
+    for (server, err) in unavailable {
+        let _ = writeln!(
+            message,
+            "{} server ({} port {}): {}",
+            server.name, server.protocol, server.port, err
+        );
+    }
+
+    message.push_str(
+        "\nAnother program (or another copy of this client) is using these ports, \
+        close it and try again.",
+    );
+    message
+}
+
+/// Creates a short description of the ports used by the local servers
+pub fn server_ports_text() -> String {
+    LOCAL_SERVERS
+        .iter()
+        .map(|server| format!("{} {}", server.name, server.port))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
 
 /// Starts all the servers in their own tasks
 ///
 /// ## Arguments
-/// * `ctx` - The client context
-pub fn start_all_servers(ctx: Arc<ClientContext>) {
+/// * `ctx`    - The client context
+/// * `tunnel` - The tunnel mode to use
+pub fn start_all_servers(ctx: Arc<ClientContext>, tunnel: TunnelMode) {
+    // Check the ports are available before starting any servers, running
+    // servers are still holding their ports so the check is skipped
+    let unavailable = if has_server_tasks() {
+        debug!("Skipping port check, servers are already running");
+        Vec::new()
+    } else {
+        check_server_ports()
+    };
+
+    if !unavailable.is_empty() {
+        for (server, err) in &unavailable {
+            error!(
+                "{} port {} for the {} server is unavailable: {}",
+                server.protocol, server.port, server.name, err
+            );
+        }
+
+        show_error(
+            "Local server ports unavailable",
+            &server_ports_message(&unavailable),
+        );
+    }
+
+    // Servers with unavailable ports are skipped as they would fail to start
+    let is_available = |name: &str| !unavailable.iter().any(|(server, _)| server.name == name);
+
     // Stop existing servers and tasks if they are running
-    stop_server_tasks();
+    stop_all_servers();
+
+    for (server, _) in &unavailable {
+        set_server_state(server.name, ServerState::Unavailable);
+    }
 
     // Spawn redirector server
-    let redirector = redirector::start_redirector_server();
-    run_server(redirector, "redirector");
+    if is_available("redirector") {
+        let redirector = redirector::start_redirector_server();
+        run_server(redirector, "redirector");
+    }
 
     // Spawn blaze server
-    let blaze = blaze::start_blaze_server(ctx.clone());
-    run_server(blaze, "blaze");
+    if is_available("blaze") {
+        let blaze = blaze::start_blaze_server(ctx.clone());
+        run_server(blaze, "blaze");
+    }
 
     // Spawn http proxy server
-    let http = http::start_http_server(ctx.clone());
-    run_server(http, "http");
+    if is_available("http") {
+        let http = http::start_http_server(ctx.clone());
+        run_server(http, "http");
+    }
 
     // Spawn QoS server
-    let qos = qos::start_qos_server();
-    run_server(qos, "qos");
+    if is_available("qos") {
+        let qos = qos::start_qos_server();
+        run_server(qos, "qos");
+    }
 
     // Spawn tunnel server
-    let tunnel = start_tunnel_server(ctx.clone());
-    run_server(tunnel, "tunnel");
+    if tunnel == TunnelMode::Disabled {
+        debug!("Tunnel server is disabled");
+        set_server_state("tunnel", ServerState::Disabled);
+    } else if is_available("tunnel") {
+        let tunnel = start_tunnel_server(ctx.clone(), tunnel);
+        run_server(tunnel, "tunnel");
+    }
 
     // Spawn telemetry server
-    let telemetry = telemetry::start_telemetry_server(ctx);
-    run_server(telemetry, "telemetry");
+    if is_available("telemetry") {
+        let telemetry = telemetry::start_telemetry_server(ctx);
+        run_server(telemetry, "telemetry");
+    }
 }
 
 /// Runs the tunnel server, if a tunnel port is available a UDP tunnel will be
 /// attempted, if that fails or a tunnel port is unavailable an HTTP tunnel
 /// will be attempted instead
-async fn start_tunnel_server(ctx: Arc<ClientContext>) -> std::io::Result<()> {
+///
+/// ## Arguments
+/// * `ctx`    - The client context
+/// * `tunnel` - The tunnel mode to use
+async fn start_tunnel_server(ctx: Arc<ClientContext>, tunnel: TunnelMode) -> std::io::Result<()> {
+    // Only the HTTP upgrade tunnel can be used in HTTP mode
+    let tunnel_port = match tunnel {
+        TunnelMode::Automatic => ctx.tunnel_port,
+        TunnelMode::Http | TunnelMode::Disabled => None,
+    };
+
     // Spawn tunnel server
-    match ctx.tunnel_port {
+    match tunnel_port {
         // When UDP tunnel server port is available use the faster UDP tunnel server
         Some(tunnel_port) => {
             let err = match udp_tunnel::start_udp_tunnel_server(ctx.clone(), tunnel_port).await {
@@ -73,10 +309,15 @@ pub fn run_server<F>(future: F, name: &'static str)
 where
     F: Future<Output = std::io::Result<()>> + Send + 'static,
 {
+    set_server_state(name, ServerState::Running);
+
     spawn_server_task(async move {
         if let Err(err) = future.await {
+            set_server_state(name, ServerState::Failed);
             show_error(&format!("Failed to start {name} server"), &err.to_string());
             error!("Failed to start {name} server: {err}");
+        } else {
+            set_server_state(name, ServerState::Stopped);
         }
     });
 }
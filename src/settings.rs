@@ -0,0 +1,425 @@
+//! Settings resolved by layering the defaults, config file, environment
+//! variables and command line arguments (In that order of priority)
+
+use crate::{
+    args::Args, config::ClientConfig, dns::DEFAULT_DNS_ADDRESS, hosts::Redirect, policy::Policy,
+};
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use std::{env::var, net::SocketAddr, path::PathBuf, str::FromStr};
+use thiserror::Error;
+
+/// Environment variable for the connection URL
+pub const ENV_CONNECTION_URL: &str = "POCKET_RELAY_CONNECTION_URL";
+/// Environment variable for the client identity path
+pub const ENV_IDENTITY: &str = "POCKET_RELAY_IDENTITY";
+/// Environment variable for the config file path
+pub const ENV_CONFIG: &str = "POCKET_RELAY_CONFIG";
+/// Environment variable for the log level
+pub const ENV_LOG_LEVEL: &str = "POCKET_RELAY_LOG_LEVEL";
+/// Environment variable for the update behavior
+pub const ENV_UPDATE: &str = "POCKET_RELAY_UPDATE";
+/// Environment variable for enabling portable mode
+pub const ENV_PORTABLE: &str = "POCKET_RELAY_PORTABLE";
+/// Environment variable for running without a GUI
+pub const ENV_HEADLESS: &str = "POCKET_RELAY_HEADLESS";
+/// Environment variable for enabling the local control API
+pub const ENV_CONTROL_API: &str = "POCKET_RELAY_CONTROL_API";
+/// Environment variable for the redirect mode
+pub const ENV_REDIRECT_MODE: &str = "POCKET_RELAY_REDIRECT_MODE";
+
+/// Default path to the client identity file
+pub const DEFAULT_IDENTITY_PATH: &str = "pocket-relay-identity.p12";
+/// Default log level
+pub const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::Debug;
+
+/// Behavior for handling new client versions
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum UpdateBehavior {
+    /// Ask the user before updating
+    #[default]
+    #[serde(rename = "prompt")]
+    Prompt,
+    /// Update without asking the user
+    #[serde(rename = "auto")]
+    Automatic,
+    /// Don't check for updates
+    #[serde(rename = "off")]
+    Disabled,
+}
+
+impl FromStr for UpdateBehavior {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "prompt" => Ok(Self::Prompt),
+            "auto" => Ok(Self::Automatic),
+            "off" => Ok(Self::Disabled),
+            _ => Err(format!(
+                "unknown update behavior \"{s}\" expected prompt, auto or off"
+            )),
+        }
+    }
+}
+
+/// Mode for the tunnel server used for connections between players
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TunnelMode {
+    /// Use the UDP tunnel when the server supports it falling
+    /// back to the HTTP tunnel
+    #[default]
+    #[serde(rename = "auto")]
+    Automatic,
+    /// Only use the HTTP tunnel
+    #[serde(rename = "http")]
+    Http,
+    /// Don't run a tunnel server
+    #[serde(rename = "disabled")]
+    Disabled,
+}
+
+/// Method used to redirect the game to the client
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum RedirectMode {
+    /// Add the redirects to the hosts file
+    #[default]
+    #[serde(rename = "hosts")]
+    Hosts,
+    /// Answer the redirects using the built-in DNS server
+    #[serde(rename = "dns")]
+    Dns,
+}
+
+impl FromStr for RedirectMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hosts" => Ok(Self::Hosts),
+            "dns" => Ok(Self::Dns),
+            _ => Err(format!(
+                "unknown redirect mode \"{s}\" expected hosts or dns"
+            )),
+        }
+    }
+}
+
+/// Settings from the environment variables, values that weren't
+/// set are left as [`None`]
+#[derive(Debug, Default)]
+pub struct EnvSettings {
+    /// Connection URL to use
+    pub connection_url: Option<String>,
+    /// Path to the client identity file
+    pub identity_path: Option<PathBuf>,
+    /// Path to the config file
+    pub config_path: Option<PathBuf>,
+    /// Log level to use
+    pub log_level: Option<LevelFilter>,
+    /// Update behavior to use
+    pub update: Option<UpdateBehavior>,
+    /// Whether portable mode is enabled
+    pub portable: bool,
+    /// Whether to run without a GUI
+    pub headless: bool,
+    /// Whether to enable the local control API
+    pub control_api: Option<bool>,
+    /// Redirect mode to use
+    pub redirect_mode: Option<RedirectMode>,
+}
+
+/// Errors that could occur while reading the environment variables
+#[derive(Debug, Error)]
+#[error("Invalid value for environment variable {0}: {1}")]
+pub struct EnvError(&'static str, String);
+
+impl EnvSettings {
+    /// Reads the settings from the `POCKET_RELAY_*` environment variables
+    pub fn from_env() -> Result<Self, EnvError> {
+        Ok(Self {
+            connection_url: env_value(ENV_CONNECTION_URL),
+            identity_path: env_value(ENV_IDENTITY).map(PathBuf::from),
+            config_path: env_value(ENV_CONFIG).map(PathBuf::from),
+            log_level: env_parse(ENV_LOG_LEVEL)?,
+            update: env_parse(ENV_UPDATE)?,
+            portable: env_parse::<EnvBool>(ENV_PORTABLE)?.is_some_and(|value| value.0),
+            headless: env_parse::<EnvBool>(ENV_HEADLESS)?.is_some_and(|value| value.0),
+            control_api: env_parse::<EnvBool>(ENV_CONTROL_API)?.map(|value| value.0),
+            redirect_mode: env_parse(ENV_REDIRECT_MODE)?,
+        })
+    }
+}
+
+/// Reads the value of the environment variable `name` ignoring empty values
+///
+/// ## Arguments
+/// * `name` - The name of the environment variable
+fn env_value(name: &str) -> Option<String> {
+    var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Reads and parses the value of the environment variable `name`
+///
+/// ## Arguments
+/// * `name` - The name of the environment variable
+fn env_parse<T>(name: &'static str) -> Result<Option<T>, EnvError>
+where
+    T: FromStr,
+    T::Err: ToString,
+{
+    env_value(name)
+        .map(|value| value.parse())
+        .transpose()
+        .map_err(|err: T::Err| EnvError(name, err.to_string()))
+}
+
+/// Boolean environment variable value
+struct EnvBool(bool);
+
+impl FromStr for EnvBool {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(Self(true)),
+            "0" | "false" | "no" | "off" => Ok(Self(false)),
+            _ => Err(format!("expected a boolean value but got \"{s}\"")),
+        }
+    }
+}
+
+/// Settings resolved from all the different layers
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// Connection URL to use instead of the saved profiles
+    pub connection_url: Option<String>,
+    /// Whether to connect to the connection URL immediately
+    #[cfg(any(feature = "iced", feature = "native"))]
+    pub auto_connect: bool,
+    /// Whether the connection URL is locked by the policy
+    pub connection_locked: bool,
+    /// Whether the "Save connection URL" option is hidden by the policy
+    pub hide_remember: bool,
+    /// Path to the client identity file
+    pub identity_path: PathBuf,
+    /// Whether the identity path was explicitly provided rather than
+    /// being the default path
+    pub identity_explicit: bool,
+    /// Log level to use
+    pub log_level: LevelFilter,
+    /// Update behavior to use
+    pub update: UpdateBehavior,
+    /// Tunnel mode to use
+    pub tunnel: TunnelMode,
+    /// Whether to enable the local control API
+    pub control_api: bool,
+    /// Redirects to apply to the hosts file
+    pub redirects: Vec<Redirect>,
+    /// Method used to apply the redirects
+    pub redirect_mode: RedirectMode,
+    /// Address for the DNS server to listen on
+    pub dns_address: SocketAddr,
+    /// Upstream DNS server to forward queries to, the system
+    /// DNS server is used when not set
+    pub dns_upstream: Option<SocketAddr>,
+    /// Values from the layers above the config file, kept so the settings
+    /// can be resolved again when the config file changes
+    overrides: Overrides,
+}
+
+/// Values from the environment variables, command line arguments and
+/// policy that take priority over the config file
+#[derive(Debug, Default, Clone)]
+struct Overrides {
+    /// Path to the client identity file
+    identity_path: Option<PathBuf>,
+    /// Log level to use
+    log_level: Option<LevelFilter>,
+    /// Update behavior to use
+    update: Option<UpdateBehavior>,
+    /// Tunnel mode to use
+    tunnel: Option<TunnelMode>,
+    /// Whether to enable the local control API
+    control_api: Option<bool>,
+    /// Redirect mode to use
+    redirect_mode: Option<RedirectMode>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            connection_url: None,
+            #[cfg(any(feature = "iced", feature = "native"))]
+            auto_connect: false,
+            connection_locked: false,
+            hide_remember: false,
+            identity_path: PathBuf::from(DEFAULT_IDENTITY_PATH),
+            identity_explicit: false,
+            log_level: DEFAULT_LOG_LEVEL,
+            update: UpdateBehavior::default(),
+            tunnel: TunnelMode::default(),
+            control_api: false,
+            redirects: Redirect::defaults(),
+            redirect_mode: RedirectMode::default(),
+            dns_address: DEFAULT_DNS_ADDRESS,
+            dns_upstream: None,
+            overrides: Overrides::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Provides the address of the DNS server when the DNS
+    /// redirect mode is used
+    pub fn dns_server(&self) -> Option<SocketAddr> {
+        (self.redirect_mode == RedirectMode::Dns).then_some(self.dns_address)
+    }
+
+    /// Resolves the settings from the `config` file, `env` variables and
+    /// command line `args`, later layers take priority. Values locked by
+    /// the `policy` take priority over all the layers
+    ///
+    /// ## Arguments
+    /// * `config` - The config file if one was loaded
+    /// * `env`    - The environment variable settings
+    /// * `args`   - The command line arguments
+    /// * `policy` - The loaded policy
+    pub fn resolve(
+        config: Option<&ClientConfig>,
+        env: EnvSettings,
+        args: &Args,
+        policy: &Policy,
+    ) -> Self {
+        // Connection links take priority over the other connection URLs
+        let link = args.link.as_ref();
+        let connection_url = policy
+            .connection_url
+            .clone()
+            .or_else(|| link.map(|link| link.url.clone()))
+            .or_else(|| args.connection_url.clone())
+            .or(env.connection_url);
+
+        let overrides = Overrides {
+            identity_path: args.identity_path.clone().or(env.identity_path),
+            log_level: args.log_level.or(env.log_level),
+            update: if policy.disable_updates {
+                Some(UpdateBehavior::Disabled)
+            } else {
+                args.update.or(env.update)
+            },
+            tunnel: policy.tunnel,
+            control_api: args.control_api.then_some(true).or(env.control_api),
+            redirect_mode: args.redirect_mode.or(env.redirect_mode),
+        };
+
+        let mut settings = Self {
+            connection_url,
+            #[cfg(any(feature = "iced", feature = "native"))]
+            auto_connect: link.is_some_and(|link| link.connect),
+            connection_locked: policy.connection_url.is_some(),
+            hide_remember: policy.hide_remember,
+            overrides,
+            ..Self::default()
+        };
+        settings.apply_config(config);
+        settings
+    }
+
+    /// Resolves the settings that can be set by the `config` file
+    ///
+    /// ## Arguments
+    /// * `config` - The config file if one was loaded
+    fn apply_config(&mut self, config: Option<&ClientConfig>) {
+        let overrides = &self.overrides;
+        let identity_path = overrides
+            .identity_path
+            .clone()
+            .or_else(|| config.and_then(|config| config.identity_path.clone()));
+
+        self.identity_explicit = identity_path.is_some();
+        self.identity_path = identity_path.unwrap_or_else(|| PathBuf::from(DEFAULT_IDENTITY_PATH));
+        self.log_level = overrides
+            .log_level
+            .or(config.and_then(|config| config.log_level))
+            .unwrap_or(DEFAULT_LOG_LEVEL);
+        self.update = overrides
+            .update
+            .or(config.and_then(|config| config.update))
+            .unwrap_or_default();
+        self.tunnel = overrides
+            .tunnel
+            .or(config.and_then(|config| config.tunnel))
+            .unwrap_or_default();
+        self.control_api = overrides
+            .control_api
+            .or(config.and_then(|config| config.control_api))
+            .unwrap_or_default();
+        self.redirects = config
+            .and_then(|config| config.redirects.clone())
+            .map(dedup_redirects)
+            .unwrap_or_else(Redirect::defaults);
+        self.redirect_mode = overrides
+            .redirect_mode
+            .or(config.and_then(|config| config.redirect_mode))
+            .unwrap_or_default();
+        self.dns_address = config
+            .and_then(|config| config.dns_address)
+            .unwrap_or(DEFAULT_DNS_ADDRESS);
+        self.dns_upstream = config.and_then(|config| config.dns_upstream);
+    }
+
+    /// Resolves the settings again after the `config` file changed. The log
+    /// level, update behavior and tunnel mode are updated, the tunnel mode
+    /// is used from the next connection. The other settings are only used
+    /// at startup so they're left unchanged and the names of the ones that
+    /// changed are returned as they require restarting the client
+    ///
+    /// ## Arguments
+    /// * `config` - The changed config file
+    #[cfg(any(feature = "iced", feature = "native"))]
+    pub fn reload(&mut self, config: &ClientConfig) -> Vec<&'static str> {
+        let mut reloaded = self.clone();
+        reloaded.apply_config(Some(config));
+
+        self.log_level = reloaded.log_level;
+        self.update = reloaded.update;
+        self.tunnel = reloaded.tunnel;
+
+        [
+            (
+                "identity_path",
+                reloaded.identity_path != self.identity_path,
+            ),
+            ("control_api", reloaded.control_api != self.control_api),
+            ("redirects", reloaded.redirects != self.redirects),
+            (
+                "redirect_mode",
+                reloaded.redirect_mode != self.redirect_mode,
+            ),
+            ("dns_address", reloaded.dns_address != self.dns_address),
+            ("dns_upstream", reloaded.dns_upstream != self.dns_upstream),
+        ]
+        .into_iter()
+        .filter_map(|(name, changed)| changed.then_some(name))
+        .collect()
+    }
+}
+
+/// Removes redirects for host names that are already redirected, the
+/// first redirect for each host name is kept
+///
+/// ## Arguments
+/// * `redirects` - The redirects from the config file
+fn dedup_redirects(redirects: Vec<Redirect>) -> Vec<Redirect> {
+    let mut output: Vec<Redirect> = Vec::with_capacity(redirects.len());
+    for redirect in redirects {
+        if !output
+            .iter()
+            .any(|existing| existing.matches(&redirect.host))
+        {
+            output.push(redirect);
+        }
+    }
+    output
+}
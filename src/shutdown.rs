@@ -0,0 +1,126 @@
+//! Cleanup for when the client is stopped by a signal, a panic or by
+//! exiting the process directly, ensures the hosts file entry is removed
+
+use crate::{hosts, servers::stop_all_servers, ui::is_headless};
+use log::{debug, error};
+use std::panic;
+
+/// Stops the servers, removes the hosts file entry and exits the
+/// process with the provided exit `code`
+///
+/// ## Arguments
+/// * `code` - The process exit code
+pub fn exit(code: i32) -> ! {
+    stop_all_servers();
+    hosts::cleanup();
+    std::process::exit(code);
+}
+
+/// Installs the panic hook and signal handlers that perform the cleanup
+pub fn install_handlers() {
+    install_panic_hook();
+
+    let result = std::thread::Builder::new()
+        .name("signal-handler".to_string())
+        .spawn(|| {
+            let runtime = match tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                Ok(value) => value,
+                Err(err) => {
+                    error!("Failed to create signal handler runtime: {}", err);
+                    return;
+                }
+            };
+
+            let code = runtime.block_on(wait_for_signal());
+
+            if is_headless() {
+                println!("Stopping...");
+            }
+
+            exit(code);
+        });
+
+    if let Err(err) = result {
+        error!("Failed to spawn signal handler: {}", err);
+    }
+}
+
+/// Installs a panic hook that performs the cleanup when the main thread
+/// panics, panics on other threads (i.e. server tasks) don't stop the
+/// client so they are ignored
+fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        if std::thread::current().name() == Some("main") {
+            hosts::cleanup();
+        }
+
+        default_hook(info);
+    }));
+}
+
+/// Waits for a signal that would stop the process, provides the
+/// exit code for the signal
+#[cfg(target_family = "unix")]
+async fn wait_for_signal() -> i32 {
+    use tokio::signal::unix::{signal, Signal, SignalKind};
+
+    /// Creates a listener for the signal `kind` logging failures
+    fn listen(kind: SignalKind, name: &str) -> Option<Signal> {
+        signal(kind)
+            .map_err(|err| error!("Failed to listen for {}: {}", name, err))
+            .ok()
+    }
+
+    /// Waits for the signal, listeners that failed to be created never complete
+    async fn recv(signal: &mut Option<Signal>) {
+        match signal {
+            Some(signal) => {
+                signal.recv().await;
+            }
+            None => std::future::pending().await,
+        }
+    }
+
+    let mut interrupt = listen(SignalKind::interrupt(), "SIGINT");
+    let mut terminate = listen(SignalKind::terminate(), "SIGTERM");
+    let mut hangup = listen(SignalKind::hangup(), "SIGHUP");
+
+    let (name, code) = tokio::select! {
+        _ = recv(&mut interrupt) => ("SIGINT", 130),
+        _ = recv(&mut terminate) => ("SIGTERM", 143),
+        _ = recv(&mut hangup) => ("SIGHUP", 129),
+    };
+
+    debug!("Received {}, cleaning up", name);
+    code
+}
+
+/// Waits for a signal that would stop the process, provides the
+/// exit code for the signal
+#[cfg(target_family = "windows")]
+async fn wait_for_signal() -> i32 {
+    use tokio::signal::windows::{ctrl_break, ctrl_c, ctrl_close};
+
+    let (mut ctrl_c, mut ctrl_break, mut ctrl_close) = match (ctrl_c(), ctrl_break(), ctrl_close())
+    {
+        (Ok(a), Ok(b), Ok(c)) => (a, b, c),
+        _ => {
+            error!("Failed to listen for console signals");
+            return std::future::pending().await;
+        }
+    };
+
+    tokio::select! {
+        _ = ctrl_c.recv() => debug!("Received Ctrl+C, cleaning up"),
+        _ = ctrl_break.recv() => debug!("Received Ctrl+Break, cleaning up"),
+        _ = ctrl_close.recv() => debug!("Received console close, cleaning up"),
+    }
+
+    // STATUS_CONTROL_C_EXIT
+    0xC000013Au32 as i32
+}
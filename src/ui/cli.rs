@@ -0,0 +1,239 @@
+//! Headless terminal variant, prints the connection status to the terminal
+//! and handles messages through stdin/stdout instead of dialogs
+
+use crate::{
+    config::{write_config_file, ClientConfig},
+    control::{self, set_connection_status, ConnectionStatus, ControlCommand},
+    core::{api::lookup_server, ctx::ClientContext, reqwest},
+    history::{read_history_file, write_history_file, HistoryEntry},
+    servers::{server_ports_text, start_all_servers, stop_all_servers},
+    settings::Settings,
+    ui::WINDOW_TITLE,
+    update,
+};
+use std::{
+    io::{stdin, stdout, Write},
+    process::ExitCode,
+    sync::Arc,
+};
+use tokio::sync::mpsc;
+
+/// Runs the client headless until it receives a shutdown signal
+///
+/// ## Arguments
+/// * `config`   - The client config to use
+/// * `settings` - The resolved settings to use
+/// * `client`   - The HTTP client to use
+pub fn init(config: Option<ClientConfig>, settings: Settings, client: reqwest::Client) -> ExitCode {
+    // Create tokio async runtime
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Failed building tokio runtime");
+
+    let code = runtime.block_on(run(config, settings, client));
+
+    // Don't wait for tasks blocked on stdin (i.e. update prompts)
+    runtime.shutdown_background();
+
+    code
+}
+
+/// Connects to the server and runs the local servers until a
+/// shutdown signal is received
+///
+/// ## Arguments
+/// * `config`   - The client config to use
+/// * `settings` - The resolved settings to use
+/// * `client`   - The HTTP client to use
+async fn run(
+    config: Option<ClientConfig>,
+    settings: Settings,
+    client: reqwest::Client,
+) -> ExitCode {
+    println!("{WINDOW_TITLE}");
+
+    // Spawn the updating task
+    tokio::spawn(update::update(client.clone(), settings.update));
+
+    let control_api = settings.control_api;
+    let mut app = HeadlessApp {
+        remember: config.is_some() && !settings.hide_remember,
+        config: config.unwrap_or_default(),
+        settings,
+        http_client: client,
+    };
+
+    // Use the provided connection URL or the most recently used profile
+    let target = app.settings.connection_url.clone().or_else(|| {
+        app.config
+            .last_used_profile()
+            .map(|profile| profile.connection_url.clone())
+    });
+
+    // Spawn the control API, the sender is kept when disabled so waiting
+    // for commands never completes
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    if control_api {
+        tokio::spawn(control::serve(
+            app.settings.clone(),
+            app.http_client.clone(),
+            tx.clone(),
+        ));
+    }
+
+    match target {
+        Some(target) => {
+            // Without the control API there is nothing to do after failing
+            if !app.connect(target).await && !control_api {
+                return ExitCode::FAILURE;
+            }
+        }
+        None if control_api => println!("Waiting for a connect command from the control API"),
+        None => {
+            show_error(
+                "Missing connection URL",
+                "Provide a connection URL using --connect <URL> when running headless",
+            );
+            return ExitCode::from(2);
+        }
+    }
+
+    println!("You must keep this program running while playing, press Ctrl+C to stop");
+
+    // Commands are handled until the client is stopped by a signal
+    while let Some(command) = rx.recv().await {
+        match command {
+            ControlCommand::Connect(url) => {
+                app.connect(url).await;
+            }
+            ControlCommand::Disconnect => {
+                stop_all_servers();
+                set_connection_status(ConnectionStatus::Disconnected);
+                println!("Disconnected");
+            }
+        }
+    }
+
+    std::future::pending().await
+}
+
+/// State for the headless client
+struct HeadlessApp {
+    /// Whether to remember the connection URL
+    remember: bool,
+    /// The client config containing the saved profiles
+    config: ClientConfig,
+    /// The resolved settings
+    settings: Settings,
+    /// Http client for sending requests
+    http_client: reqwest::Client,
+}
+
+impl HeadlessApp {
+    /// Connects to the server at the `target` connection URL and starts
+    /// the local servers, returns whether the connection succeeded
+    ///
+    /// ## Arguments
+    /// * `target` - The connection URL
+    async fn connect(&mut self, target: String) -> bool {
+        println!("Connecting to {target}...");
+        set_connection_status(ConnectionStatus::Connecting {
+            url: target.clone(),
+        });
+
+        let result = lookup_server(self.http_client.clone(), target.clone()).await;
+
+        // Record the attempt in the connection history, history isn't saved
+        // when remembering is hidden by the policy
+        let entry = HistoryEntry::new(&target, &result);
+        set_connection_status(ConnectionStatus::from(&entry));
+
+        if !self.settings.hide_remember {
+            let mut history = read_history_file();
+            history.push(entry);
+            write_history_file(&history);
+        }
+
+        let mut lookup = match result {
+            Ok(value) => value,
+            Err(err) => {
+                show_error("Failed to connect", &err.to_string());
+                return false;
+            }
+        };
+
+        let ctx = Arc::new(ClientContext {
+            http_client: self.http_client.clone(),
+            base_url: lookup.url.clone(),
+            association: lookup.association.take(),
+            tunnel_port: lookup.tunnel_port,
+        });
+
+        // Start the servers
+        start_all_servers(ctx, self.settings.tunnel);
+
+        // Save the connection URL as a profile
+        if self.remember {
+            self.config.save_profile(lookup.url.as_str());
+            self.config.sort_profiles();
+            write_config_file(&self.config);
+        }
+
+        println!(
+            "Connected: {} {} version v{}",
+            lookup.url.scheme(),
+            lookup.url.authority(),
+            lookup.version
+        );
+        println!("Local ports: {}", server_ports_text());
+
+        true
+    }
+}
+
+/// Prints an info message to the terminal
+///
+/// ## Arguments
+/// * `title` - The title of the message
+/// * `text`  - The message text
+pub fn show_info(title: &str, text: &str) {
+    println!("{title}: {text}");
+}
+
+/// Prints an error message to the terminal
+///
+/// ## Arguments
+/// * `title` - The title of the message
+/// * `text`  - The message text
+pub fn show_error(title: &str, text: &str) {
+    eprintln!("Error: {title}: {text}");
+}
+
+/// Prints a warning message to the terminal
+///
+/// ## Arguments
+/// * `title` - The title of the message
+/// * `text`  - The message text
+pub fn show_warning(title: &str, text: &str) {
+    eprintln!("Warning: {title}: {text}");
+}
+
+/// Asks the user to confirm using stdin returning the choice that the
+/// user made, anything other than yes is treated as no (Including when
+/// stdin is closed)
+///
+/// ## Arguments
+/// * `title` - The title of the message
+/// * `text`  - The message text
+pub fn show_confirm(title: &str, text: &str) -> bool {
+    print!("{title}: {text} [y/N] ");
+    let _ = stdout().flush();
+
+    let mut line = String::new();
+    if stdin().read_line(&mut line).is_err() {
+        return false;
+    }
+
+    matches!(line.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
@@ -1,37 +1,46 @@
 use crate::{
-    config::{write_config_file, ClientConfig},
+    config::{write_config_file, ClientConfig, ConfigWatcher, ServerProfile},
+    control::{self, set_connection_status, ConnectionStatus, ControlCommand},
     core::{
         api::{lookup_server, LookupData, LookupError},
         ctx::ClientContext,
         reqwest,
     },
-    servers::start_all_servers,
-    ui::show_error,
+    doctor::{run_diagnostics, DoctorReport},
+    history::{read_history_file, write_history_file, ConnectionHistory, HistoryEntry},
+    link::ConnectionLink,
+    servers::{server_ports_text, start_all_servers, stop_all_servers},
+    settings::Settings,
+    ui::{reload_settings, show_error, show_info, show_warning},
     ui::{ICON_BYTES, WINDOW_TITLE},
     update,
 };
 use iced::{
-    executor,
+    clipboard, executor,
+    futures::SinkExt,
+    subscription,
     theme::Palette,
     widget::{
-        button, checkbox, column, container, row, text, text_input, Button, Column, Row, Text,
-        TextInput,
+        button, checkbox, column, container, pick_list, row, text, text_input, Button, Column,
+        PickList, Row, Text, TextInput,
     },
     window::{self, icon},
-    Application, Color, Command, Length, Settings, Theme,
+    Alignment, Application, Color, Command, Length, Subscription, Theme,
 };
 use std::sync::Arc;
+use tokio::sync::mpsc;
 
 /// The window size
-pub const WINDOW_SIZE: (u32, u32) = (500, 200);
+pub const WINDOW_SIZE: (u32, u32) = (500, 360);
 
 /// Initializes the user interface
 ///
 /// ## Arguments
-/// * `config` - The client config to use
-/// * `client` - The HTTP client to use
-pub fn init(config: Option<ClientConfig>, client: reqwest::Client) {
-    App::run(Settings {
+/// * `config`   - The client config to use
+/// * `settings` - The resolved settings to use
+/// * `client`   - The HTTP client to use
+pub fn init(config: Option<ClientConfig>, settings: Settings, client: reqwest::Client) {
+    App::run(iced::Settings {
         window: window::Settings {
             icon: icon::from_file_data(ICON_BYTES, None).ok(),
             size: WINDOW_SIZE,
@@ -39,8 +48,8 @@ pub fn init(config: Option<ClientConfig>, client: reqwest::Client) {
 
             ..window::Settings::default()
         },
-        flags: (config, client),
-        ..Settings::default()
+        flags: (config, settings, client),
+        ..iced::Settings::default()
     })
     .unwrap();
 }
@@ -53,8 +62,18 @@ struct App {
     remember: bool,
     /// The current connection URL
     target: String,
+    /// The client config containing the saved profiles
+    config: ClientConfig,
+    /// Index of the selected profile if the target matches one
+    selected_profile: Option<usize>,
+    /// History of recent connection attempts
+    history: ConnectionHistory,
+    /// Whether the diagnostics are currently running
+    troubleshooting: bool,
     /// Http client for sending requests
     http_client: reqwest::Client,
+    /// The resolved settings
+    settings: Settings,
 }
 
 /// Messages used for updating the game state
@@ -66,8 +85,28 @@ enum AppMessage {
     UpdateTarget,
     /// Message for setting the current lookup result state
     LookupState(LookupState),
+    /// A lookup has completed and its history entry should be saved
+    LookupComplete(HistoryEntry, LookupState),
     /// The remember checkbox button has changed
     RememberChanged(bool),
+    /// A saved profile was picked from the profile list
+    ProfileSelected(ServerProfile),
+    /// The favourite checkbox for the selected profile has changed
+    FavouriteChanged(bool),
+    /// The config file was changed outside of the client
+    ConfigChanged(ClientConfig),
+    /// The connection link for the target should be copied
+    CopyLink,
+    /// An entry was picked from the connection history
+    HistorySelected(HistoryEntry),
+    /// The connection history should be cleared
+    ClearHistory,
+    /// The diagnostics should be run
+    Troubleshoot,
+    /// The diagnostics have completed
+    TroubleshootComplete(DoctorReport),
+    /// Command received from the control API
+    Control(ControlCommand),
 }
 
 /// Different states that lookup process can be in
@@ -83,30 +122,79 @@ enum LookupState {
     Error,
 }
 
+impl App {
+    /// Saves the profile from a connection `link` and uses its connection
+    /// URL as the target, connecting immediately if requested by the link
+    ///
+    /// ## Arguments
+    /// * `link` - The connection link
+    fn apply_link(&mut self, link: ConnectionLink) -> Command<AppMessage> {
+        // Profiles aren't saved when remembering is hidden by the policy
+        if !self.settings.hide_remember {
+            self.config.add_profile(&link.url, link.name);
+            self.config.sort_profiles();
+            write_config_file(&self.config);
+        }
+
+        self.target = link.url;
+        self.selected_profile = self.config.find_profile(&self.target);
+
+        if link.connect {
+            return self.update(AppMessage::UpdateTarget);
+        }
+
+        Command::none()
+    }
+}
+
 impl Application for App {
     type Message = AppMessage;
     type Executor = executor::Default;
-    type Flags = (Option<ClientConfig>, reqwest::Client);
+    type Flags = (Option<ClientConfig>, Settings, reqwest::Client);
     type Theme = Theme;
 
     fn new(flags: Self::Flags) -> (Self, Command<Self::Message>) {
-        let (config, http_client) = flags;
-        let (target, remember) = config
-            .map(|value| (value.connection_url, true))
+        let (config, settings, http_client) = flags;
+        let remember = config.is_some() && !settings.hide_remember;
+        let mut config = config.unwrap_or_default();
+        config.sort_profiles();
+
+        // Use the provided connection URL or the most recently used profile
+        // as the initial target
+        let target = settings
+            .connection_url
+            .clone()
+            .or_else(|| {
+                config
+                    .last_used_profile()
+                    .map(|profile| profile.connection_url.clone())
+            })
             .unwrap_or_default();
+        let selected_profile = config.find_profile(&target);
 
         // Spawn the update checking task
-        tokio::spawn(update::update(http_client.clone()));
-
-        (
-            App {
-                lookup_result: LookupState::None,
-                target,
-                remember,
-                http_client,
-            },
-            Command::none(),
-        )
+        tokio::spawn(update::update(http_client.clone(), settings.update));
+
+        let mut app = App {
+            lookup_result: LookupState::None,
+            target,
+            config,
+            selected_profile,
+            history: read_history_file(),
+            troubleshooting: false,
+            remember,
+            http_client,
+            settings,
+        };
+
+        // Connect immediately when requested by a connection link
+        let command = if app.settings.auto_connect {
+            app.update(AppMessage::UpdateTarget)
+        } else {
+            Command::none()
+        };
+
+        (app, command)
     }
 
     fn title(&self) -> String {
@@ -116,7 +204,22 @@ impl Application for App {
     fn update(&mut self, message: Self::Message) -> Command<Self::Message> {
         match message {
             // Update the stored target
-            AppMessage::TargetChanged(value) => self.target = value,
+            AppMessage::TargetChanged(value) => {
+                // Target can't be changed when locked by the policy
+                if self.settings.connection_locked {
+                    return Command::none();
+                }
+
+                // Handle pasted connection links
+                if ConnectionLink::is_link(&value) {
+                    if let Ok(link) = value.parse::<ConnectionLink>() {
+                        return self.apply_link(link);
+                    }
+                }
+
+                self.selected_profile = self.config.find_profile(&value);
+                self.target = value;
+            }
             // Handle new target being set
             AppMessage::UpdateTarget => {
                 // Don't try to lookup if already looking up
@@ -127,9 +230,13 @@ impl Application for App {
                 self.lookup_result = LookupState::Loading;
 
                 let target = self.target.clone();
+                set_connection_status(ConnectionStatus::Connecting {
+                    url: target.clone(),
+                });
 
                 // Handling for once the async lookup is complete
-                let post_lookup = |result: Result<LookupData, LookupError>| {
+                let post_lookup = |(target, result): (String, Result<LookupData, LookupError>)| {
+                    let entry = HistoryEntry::new(&target, &result);
                     let result = match result {
                         Ok(value) => LookupState::Success(value),
                         Err(err) => {
@@ -137,14 +244,30 @@ impl Application for App {
                             LookupState::Error
                         }
                     };
-                    AppMessage::LookupState(result)
+                    AppMessage::LookupComplete(entry, result)
+                };
+
+                let http_client = self.http_client.clone();
+                let lookup = async move {
+                    let result = lookup_server(http_client, target.clone()).await;
+                    (target, result)
                 };
 
                 // Perform the async lookup with the callback
-                return Command::perform(
-                    lookup_server(self.http_client.clone(), target),
-                    post_lookup,
-                );
+                return Command::perform(lookup, post_lookup);
+            }
+
+            // Lookup completed
+            AppMessage::LookupComplete(entry, value) => {
+                set_connection_status(ConnectionStatus::from(&entry));
+
+                // History isn't saved when remembering is hidden by the policy
+                if !self.settings.hide_remember {
+                    self.history.push(entry);
+                    write_history_file(&self.history);
+                }
+
+                return self.update(AppMessage::LookupState(value));
             }
 
             // Lookup result changed
@@ -158,13 +281,17 @@ impl Application for App {
                     });
 
                     // Start all the servers
-                    start_all_servers(ctx);
+                    start_all_servers(ctx, self.settings.tunnel);
 
-                    // Save the connection URL
+                    // Save the connection URL as a profile
                     if self.remember {
                         let connection_url = value.url.to_string();
 
-                        write_config_file(ClientConfig { connection_url });
+                        self.config.save_profile(&connection_url);
+                        self.config.sort_profiles();
+                        self.selected_profile = self.config.find_profile(&self.target);
+
+                        write_config_file(&self.config);
                     }
                 }
 
@@ -173,10 +300,176 @@ impl Application for App {
 
             // Remember value changed
             AppMessage::RememberChanged(value) => self.remember = value,
+
+            // Saved profile picked
+            AppMessage::ProfileSelected(profile) => {
+                if self.settings.connection_locked {
+                    return Command::none();
+                }
+
+                self.selected_profile = self.config.find_profile(&profile.connection_url);
+                self.target = profile.connection_url;
+            }
+
+            // Favourite value changed
+            AppMessage::FavouriteChanged(value) => {
+                if self.settings.hide_remember {
+                    return Command::none();
+                }
+
+                if let Some(index) = self.selected_profile {
+                    self.config.profiles[index].favourite = value;
+                    self.config.sort_profiles();
+                    self.selected_profile = self.config.find_profile(&self.target);
+
+                    write_config_file(&self.config);
+                }
+            }
+
+            // Config file changed
+            AppMessage::ConfigChanged(mut config) => {
+                config.sort_profiles();
+
+                // Ignore changes from the client saving the config
+                if config == self.config {
+                    return Command::none();
+                }
+
+                reload_settings(&mut self.settings, &config, &self.http_client);
+
+                // Update the target if the URL of its profile changed
+                let changed_url = config
+                    .changed_profile_url(&self.config, &self.target)
+                    .map(str::to_string);
+
+                self.config = config;
+
+                // Target can't be changed when locked by the policy
+                let changed_url = changed_url.filter(|_| !self.settings.connection_locked);
+
+                if let Some(changed_url) = changed_url {
+                    self.target = changed_url;
+                    self.selected_profile = self.config.find_profile(&self.target);
+
+                    // Reconnect using the new URL if connected
+                    if self.config.reconnect_on_change
+                        && matches!(self.lookup_result, LookupState::Success(_))
+                    {
+                        return self.update(AppMessage::UpdateTarget);
+                    }
+                } else {
+                    self.selected_profile = self.config.find_profile(&self.target);
+                }
+            }
+
+            // Copy connection link
+            AppMessage::CopyLink => {
+                let name = self
+                    .selected_profile
+                    .and_then(|index| self.config.profiles.get(index))
+                    .map(|profile| profile.name.clone());
+                let link = ConnectionLink::new(self.target.trim().to_string(), name);
+
+                return clipboard::write(link.to_string());
+            }
+
+            // History entry picked
+            AppMessage::HistorySelected(entry) => {
+                if self.settings.connection_locked {
+                    return Command::none();
+                }
+
+                self.selected_profile = self.config.find_profile(&entry.url);
+                self.target = entry.url;
+            }
+
+            // Clear connection history
+            AppMessage::ClearHistory => {
+                self.history.clear();
+                write_history_file(&self.history);
+            }
+
+            // Run the diagnostics
+            AppMessage::Troubleshoot => {
+                if self.troubleshooting {
+                    return Command::none();
+                }
+
+                self.troubleshooting = true;
+
+                let diagnostics = run_diagnostics(
+                    self.http_client.clone(),
+                    Some(self.target.clone()),
+                    self.settings.identity_path.clone(),
+                    self.settings.identity_explicit,
+                    self.settings.redirects.clone(),
+                    self.settings.dns_server(),
+                );
+
+                return Command::perform(diagnostics, AppMessage::TroubleshootComplete);
+            }
+
+            // Connect to a server from the control API
+            AppMessage::Control(ControlCommand::Connect(url)) => {
+                self.selected_profile = self.config.find_profile(&url);
+                self.target = url;
+
+                return self.update(AppMessage::UpdateTarget);
+            }
+
+            // Disconnect from the control API
+            AppMessage::Control(ControlCommand::Disconnect) => {
+                stop_all_servers();
+                set_connection_status(ConnectionStatus::Disconnected);
+                self.lookup_result = LookupState::None;
+            }
+
+            // Diagnostics completed
+            AppMessage::TroubleshootComplete(report) => {
+                self.troubleshooting = false;
+
+                if report.has_failures() {
+                    show_warning("Troubleshooting found problems", &report.to_string());
+                } else {
+                    show_info("Troubleshooting found no problems", &report.to_string());
+                }
+            }
         }
         Command::none()
     }
 
+    fn subscription(&self) -> Subscription<Self::Message> {
+        // Watch the config file for changes
+        let config_watcher = subscription::unfold(
+            "config-watcher",
+            ConfigWatcher::default(),
+            |mut watcher| async move {
+                let config = watcher.changed().await;
+                (AppMessage::ConfigChanged(config), watcher)
+            },
+        );
+
+        if !self.settings.control_api {
+            return config_watcher;
+        }
+
+        // Run the control API forwarding its commands to the app
+        let settings = self.settings.clone();
+        let http_client = self.http_client.clone();
+        let control_api = subscription::channel("control-api", 16, |mut output| async move {
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            tokio::spawn(control::serve(settings, http_client, tx));
+
+            while let Some(command) = rx.recv().await {
+                let _ = output.send(AppMessage::Control(command)).await;
+            }
+
+            std::future::pending().await
+        });
+
+        Subscription::batch([config_watcher, control_api])
+    }
+
     fn view(&self) -> iced::Element<'_, Self::Message> {
         const DARK_TEXT: Color = Color::from_rgb(0.4, 0.4, 0.4);
         const RED_TEXT: Color = Color::from_rgb(0.8, 0.4, 0.4);
@@ -184,15 +477,72 @@ impl Application for App {
         const ORANGE_TEXT: Color = Color::from_rgb(0.8, 0.6, 0.4);
         const SPACING: u16 = 10;
 
-        let target_input: TextInput<_> = text_input("Connection URL", &self.target)
+        let selected_profile: Option<&ServerProfile> = self
+            .selected_profile
+            .and_then(|index| self.config.profiles.get(index));
+
+        let profile_list: PickList<'_, ServerProfile, _> = pick_list(
+            self.config.profiles.as_slice(),
+            selected_profile.cloned(),
+            AppMessage::ProfileSelected,
+        )
+        .placeholder("Saved servers")
+        .width(Length::Fill)
+        .padding(10);
+
+        let mut profile_row: Row<_> = row![profile_list].spacing(SPACING);
+        let locked = self.settings.connection_locked;
+        let hide_remember = self.settings.hide_remember;
+
+        // Favourite toggle for the selected profile
+        if let Some(profile) = selected_profile.filter(|_| !locked && !hide_remember) {
+            let favourite_check =
+                checkbox("Favourite", profile.favourite, AppMessage::FavouriteChanged)
+                    .text_size(16)
+                    .size(20)
+                    .spacing(SPACING);
+
+            profile_row = profile_row.push(favourite_check);
+        }
+
+        let history_list: PickList<'_, HistoryEntry, _> = pick_list(
+            self.history.entries.as_slice(),
+            None,
+            AppMessage::HistorySelected,
+        )
+        .placeholder("Recent connections")
+        .width(Length::Fill)
+        .padding(10);
+
+        let mut clear_button: Button<_> = button("Clear history").padding(10);
+        if !self.history.entries.is_empty() {
+            clear_button = clear_button.on_press(AppMessage::ClearHistory);
+        }
+
+        let history_row: Row<_> = row![history_list, clear_button].spacing(SPACING);
+
+        let mut target_input: TextInput<_> = text_input("Connection URL", &self.target)
             .padding(10)
-            .on_input(AppMessage::TargetChanged)
             .on_submit(AppMessage::UpdateTarget);
 
-        let target_text: Text =
-            text("Please put the server Connection URL below and press 'Set'").style(DARK_TEXT);
+        // Locked inputs are left without an input handler to disable them
+        if !locked {
+            target_input = target_input.on_input(AppMessage::TargetChanged);
+        }
+
+        let target_text: Text = text(if locked {
+            "The Connection URL has been set by your administrator, press 'Set' to connect"
+        } else {
+            "Please pick a saved server or put the server Connection URL below and press 'Set'"
+        })
+        .style(DARK_TEXT);
         let target_button: Button<_> = button("Set").on_press(AppMessage::UpdateTarget).padding(10);
 
+        let mut copy_button: Button<_> = button("Copy link").padding(10);
+        if !self.target.trim().is_empty() {
+            copy_button = copy_button.on_press(AppMessage::CopyLink);
+        }
+
         let status_text: Text = match &self.lookup_result {
             LookupState::None => text("Not Connected.").style(ORANGE_TEXT),
             LookupState::Loading => text("Connecting...").style(YELLOW_TEXT),
@@ -206,7 +556,20 @@ impl Application for App {
             LookupState::Error => text("Failed to connect").style(Palette::DARK.danger),
         };
 
-        let target_row: Row<_> = row![target_input, target_button].spacing(SPACING);
+        let ports_text: Text = text(format!("Local ports: {}", server_ports_text()))
+            .size(14)
+            .style(DARK_TEXT);
+
+        let mut troubleshoot_button: Button<_> = button("Troubleshoot").padding(10);
+        if !self.troubleshooting {
+            troubleshoot_button = troubleshoot_button.on_press(AppMessage::Troubleshoot);
+        }
+
+        let status_row: Row<_> = row![status_text.width(Length::Fill), troubleshoot_button]
+            .spacing(SPACING)
+            .align_items(Alignment::Center);
+
+        let target_row: Row<_> = row![target_input, target_button, copy_button].spacing(SPACING);
 
         let remember_check = checkbox(
             "Save connection URL",
@@ -224,8 +587,24 @@ impl Application for App {
         )
         .style(RED_TEXT);
 
-        let content: Column<_> =
-            column![target_text, target_row, remember_check, status_text, notice].spacing(10);
+        let mut content: Column<_> = column![target_text].spacing(10);
+
+        if !locked {
+            content = content.push(profile_row);
+
+            // Connection history isn't kept when remembering is hidden
+            if !hide_remember {
+                content = content.push(history_row);
+            }
+        }
+
+        content = content.push(target_row);
+
+        if !hide_remember {
+            content = content.push(remember_check);
+        }
+
+        let content = content.push(status_row).push(ports_text).push(notice);
 
         container(content)
             .width(Length::Fill)
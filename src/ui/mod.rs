@@ -1,3 +1,12 @@
+use crate::{config::ClientConfig, core::reqwest, settings::Settings};
+use std::{
+    process::ExitCode,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+// Headless terminal variant
+#[cfg(feature = "cli")]
+pub mod cli;
 // Iced UI variant
 #[cfg(feature = "iced")]
 pub mod iced;
@@ -5,16 +14,90 @@ pub mod iced;
 #[cfg(feature = "native")]
 pub mod native;
 
-#[cfg(feature = "iced")]
-pub use iced::init;
-#[cfg(all(feature = "native", not(feature = "iced")))]
-pub use native::init;
+// Without a GUI all messages are shown in the terminal
+#[cfg(not(any(feature = "iced", feature = "native")))]
+pub use cli::{show_confirm, show_error, show_info, show_warning};
 
 /// Title used for created windows
 pub const WINDOW_TITLE: &str = concat!("Pocket Relay Client v", env!("CARGO_PKG_VERSION"));
 /// Window icon bytes
+#[cfg(any(feature = "iced", feature = "native"))]
 pub const ICON_BYTES: &[u8] = include_bytes!("../resources/icon.ico");
 
+/// Whether the client was built with a GUI variant
+const HAS_GUI: bool = cfg!(any(feature = "iced", feature = "native"));
+
+/// Whether the client is running headless without a GUI
+static HEADLESS: AtomicBool = AtomicBool::new(!HAS_GUI);
+
+/// Sets whether the client is running headless, clients built without
+/// a GUI variant are always headless
+///
+/// ## Arguments
+/// * `value` - Whether to run headless
+pub fn set_headless(value: bool) {
+    HEADLESS.store(value || !HAS_GUI, Ordering::SeqCst);
+}
+
+/// Checks whether the client is running headless
+pub fn is_headless() -> bool {
+    HEADLESS.load(Ordering::SeqCst)
+}
+
+/// Initializes the user interface, uses the terminal variant when
+/// running headless
+///
+/// ## Arguments
+/// * `config`   - The client config to use
+/// * `settings` - The resolved settings to use
+/// * `client`   - The HTTP client to use
+pub fn init(config: Option<ClientConfig>, settings: Settings, client: reqwest::Client) -> ExitCode {
+    #[cfg(feature = "cli")]
+    if is_headless() {
+        return cli::init(config, settings, client);
+    }
+
+    #[cfg(feature = "iced")]
+    iced::init(config, settings, client);
+    #[cfg(all(feature = "native", not(feature = "iced")))]
+    native::init(config, settings, client);
+
+    ExitCode::SUCCESS
+}
+
+/// Resolves the `settings` again after the `config` file was changed,
+/// the user is told about changed settings that require a restart
+///
+/// ## Arguments
+/// * `settings` - The settings to update
+/// * `config`   - The changed config file
+/// * `client`   - The HTTP client to check for updates with
+#[cfg(any(feature = "iced", feature = "native"))]
+pub fn reload_settings(settings: &mut Settings, config: &ClientConfig, client: &reqwest::Client) {
+    use crate::{control, settings::UpdateBehavior, update};
+
+    let previous_update = settings.update;
+    let restart = settings.reload(config);
+    control::set_control_settings(settings);
+
+    log::set_max_level(settings.log_level);
+
+    // Updates are only checked at startup unless they were disabled
+    if previous_update == UpdateBehavior::Disabled && settings.update != UpdateBehavior::Disabled {
+        tokio::spawn(update::update(client.clone(), settings.update));
+    }
+
+    if !restart.is_empty() {
+        show_warning(
+            "Restart required",
+            &format!(
+                "The changes to {} in the config file will be used after restarting the client",
+                restart.join(", ")
+            ),
+        );
+    }
+}
+
 /// Shows a info message to the user.
 ///
 /// ## Arguments
@@ -23,6 +106,11 @@ pub const ICON_BYTES: &[u8] = include_bytes!("../resources/icon.ico");
 #[cfg(feature = "native")]
 #[inline]
 pub fn show_info(title: &str, text: &str) {
+    #[cfg(feature = "cli")]
+    if is_headless() {
+        return cli::show_info(title, text);
+    }
+
     native_windows_gui::simple_message(title, text);
 }
 
@@ -34,6 +122,11 @@ pub fn show_info(title: &str, text: &str) {
 #[cfg(feature = "native")]
 #[inline]
 pub fn show_error(title: &str, text: &str) {
+    #[cfg(feature = "cli")]
+    if is_headless() {
+        return cli::show_error(title, text);
+    }
+
     native_windows_gui::error_message(title, text);
 }
 
@@ -44,6 +137,11 @@ pub fn show_error(title: &str, text: &str) {
 /// * `text`  - The text for the dialog
 #[cfg(feature = "native")]
 pub fn show_warning(title: &str, text: &str) {
+    #[cfg(feature = "cli")]
+    if is_headless() {
+        return cli::show_warning(title, text);
+    }
+
     let params = native_windows_gui::MessageParams {
         title,
         content: text,
@@ -62,6 +160,11 @@ pub fn show_warning(title: &str, text: &str) {
 /// * `text`  - The text for the dialog
 #[cfg(feature = "native")]
 pub fn show_confirm(title: &str, text: &str) -> bool {
+    #[cfg(feature = "cli")]
+    if is_headless() {
+        return cli::show_confirm(title, text);
+    }
+
     let params = native_windows_gui::MessageParams {
         title,
         content: text,
@@ -77,8 +180,13 @@ pub fn show_confirm(title: &str, text: &str) -> bool {
 /// ## Arguments
 /// * `title` - The title for the dialog
 /// * `text`  - The text for the dialog
-#[cfg(not(feature = "native"))]
+#[cfg(all(feature = "iced", not(feature = "native")))]
 pub fn show_info(title: &str, text: &str) {
+    #[cfg(feature = "cli")]
+    if is_headless() {
+        return cli::show_info(title, text);
+    }
+
     native_dialog::MessageDialog::new()
         .set_title(title)
         .set_text(text)
@@ -92,8 +200,13 @@ pub fn show_info(title: &str, text: &str) {
 /// ## Arguments
 /// * `title` - The title for the dialog
 /// * `text`  - The text for the dialog
-#[cfg(not(feature = "native"))]
+#[cfg(all(feature = "iced", not(feature = "native")))]
 pub fn show_error(title: &str, text: &str) {
+    #[cfg(feature = "cli")]
+    if is_headless() {
+        return cli::show_error(title, text);
+    }
+
     native_dialog::MessageDialog::new()
         .set_title(title)
         .set_text(text)
@@ -107,8 +220,13 @@ pub fn show_error(title: &str, text: &str) {
 /// ## Arguments
 /// * `title` - The title for the dialog
 /// * `text`  - The text for the dialog
-#[cfg(not(feature = "native"))]
+#[cfg(all(feature = "iced", not(feature = "native")))]
 pub fn show_warning(title: &str, text: &str) {
+    #[cfg(feature = "cli")]
+    if is_headless() {
+        return cli::show_warning(title, text);
+    }
+
     native_dialog::MessageDialog::new()
         .set_title(title)
         .set_text(text)
@@ -123,8 +241,13 @@ pub fn show_warning(title: &str, text: &str) {
 /// ## Arguments
 /// * `title` - The title for the dialog
 /// * `text`  - The text for the dialog
-#[cfg(not(feature = "native"))]
+#[cfg(all(feature = "iced", not(feature = "native")))]
 pub fn show_confirm(title: &str, text: &str) -> bool {
+    #[cfg(feature = "cli")]
+    if is_headless() {
+        return cli::show_confirm(title, text);
+    }
+
     native_dialog::MessageDialog::new()
         .set_title(title)
         .set_text(text)
@@ -1,23 +1,29 @@
 use crate::{
-    config::{write_config_file, ClientConfig},
+    config::{write_config_file, ClientConfig, ConfigWatcher},
+    control::{self, set_connection_status, ConnectionStatus, ControlCommand},
     core::{
         api::{lookup_server, LookupData, LookupError},
         ctx::ClientContext,
         reqwest,
+        servers::has_server_tasks,
     },
-    servers::start_all_servers,
-    ui::{show_error, ICON_BYTES, WINDOW_TITLE},
+    doctor::{run_diagnostics, DoctorReport},
+    history::{read_history_file, write_history_file, ConnectionHistory, HistoryEntry},
+    link::ConnectionLink,
+    servers::{server_ports_text, start_all_servers, stop_all_servers},
+    settings::Settings,
+    ui::{reload_settings, show_error, show_info, show_warning, ICON_BYTES, WINDOW_TITLE},
     update,
 };
 use futures::FutureExt;
 use native_windows_derive::NwgUi;
 use native_windows_gui::{init as nwg_init, *};
 use std::cell::RefCell;
-use std::sync::Arc;
-use tokio::task::JoinHandle;
+use std::sync::{Arc, Mutex};
+use tokio::{sync::mpsc, task::JoinHandle};
 
 /// Size of the created window
-pub const WINDOW_SIZE: (i32, i32) = (500, 200);
+pub const WINDOW_SIZE: (i32, i32) = (500, 300);
 
 /// Native GUI app
 #[derive(NwgUi, Default)]
@@ -42,37 +48,81 @@ pub struct App {
     grid: GridLayout,
 
     /// Label for the connection URL input
-    #[nwg_control(text: "Please put the server Connection URL below and press 'Set'")]
-    #[nwg_layout_item(layout: grid, col: 0, row: 0, col_span: 2)]
+    #[nwg_control(
+        text: "Please pick a saved server or put the server Connection URL below and press 'Set'"
+    )]
+    #[nwg_layout_item(layout: grid, col: 0, row: 0, col_span: 3)]
     target_url_label: Label,
 
+    /// Picker for the saved server profiles
+    #[nwg_control]
+    #[nwg_layout_item(layout: grid, col: 0, row: 1, col_span: 2)]
+    #[nwg_events(OnComboxBoxSelection: [App::handle_profile_selected])]
+    profile_picker: ComboBox<String>,
+
+    /// Checkbox for whether the selected profile is a favourite
+    #[nwg_control(text: "Favourite")]
+    #[nwg_layout_item(layout: grid, col: 2, row: 1, col_span: 1)]
+    #[nwg_events(OnButtonClick: [App::handle_favourite])]
+    favourite_checkbox: CheckBox,
+
+    /// Picker for the recent connection history
+    #[nwg_control]
+    #[nwg_layout_item(layout: grid, col: 0, row: 2, col_span: 2)]
+    #[nwg_events(OnComboxBoxSelection: [App::handle_history_selected])]
+    history_picker: ComboBox<String>,
+
+    /// Button for clearing the connection history
+    #[nwg_control(text: "Clear history")]
+    #[nwg_layout_item(layout: grid, col: 2, row: 2, col_span: 1)]
+    #[nwg_events(OnButtonClick: [App::handle_clear_history])]
+    clear_history_button: Button,
+
     /// Input for the connection URL
     #[nwg_control(focus: true)]
-    #[nwg_layout_item(layout: grid, col: 0, row: 1, col_span: 2)]
+    #[nwg_layout_item(layout: grid, col: 0, row: 3, col_span: 2)]
+    #[nwg_events(OnTextInput: [App::handle_target_changed])]
     target_url_input: TextInput,
 
     /// Button for connecting
     #[nwg_control(text: "Set")]
-    #[nwg_layout_item(layout: grid, col: 2, row: 1, col_span: 1)]
+    #[nwg_layout_item(layout: grid, col: 2, row: 3, col_span: 1)]
     #[nwg_events(OnButtonClick: [App::handle_set])]
     set_button: Button,
 
     /// Checkbox for whether to remember the connection URL
     #[nwg_control(text: "Save connection URL")]
-    #[nwg_layout_item(layout: grid, col: 0, row: 2, col_span: 3)]
+    #[nwg_layout_item(layout: grid, col: 0, row: 4, col_span: 2)]
     remember_checkbox: CheckBox,
 
+    /// Button for copying the connection link
+    #[nwg_control(text: "Copy link")]
+    #[nwg_layout_item(layout: grid, col: 2, row: 4, col_span: 1)]
+    #[nwg_events(OnButtonClick: [App::handle_copy_link])]
+    copy_link_button: Button,
+
     /// Connection state label
     #[nwg_control(text: "Not connected")]
-    #[nwg_layout_item(layout: grid, col: 0, row: 3, col_span: 3)]
+    #[nwg_layout_item(layout: grid, col: 0, row: 5, col_span: 2)]
     connection_label: Label,
 
+    /// Button for running the diagnostics
+    #[nwg_control(text: "Troubleshoot")]
+    #[nwg_layout_item(layout: grid, col: 2, row: 5, col_span: 1)]
+    #[nwg_events(OnButtonClick: [App::handle_troubleshoot])]
+    troubleshoot_button: Button,
+
+    /// Label showing the ports used by the local servers
+    #[nwg_control]
+    #[nwg_layout_item(layout: grid, col: 0, row: 6, col_span: 3)]
+    ports_label: Label,
+
     /// Label telling the player to keep the program running
     #[nwg_control(
         text: "You must keep this program running while playing. Closing this \n\
         program will cause you to connect to the official servers instead."
     )]
-    #[nwg_layout_item(layout: grid, col: 0, row: 4, col_span: 3)]
+    #[nwg_layout_item(layout: grid, col: 0, row: 7, col_span: 3)]
     keep_running_label: Label,
 
     /// Notice for connection completion
@@ -80,14 +130,364 @@ pub struct App {
     #[nwg_events(OnNotice: [App::handle_connect_notice])]
     connect_notice: Notice,
 
+    /// Notice for the config file changing
+    #[nwg_control]
+    #[nwg_events(OnNotice: [App::handle_config_notice])]
+    config_notice: Notice,
+
+    /// Notice for diagnostics completion
+    #[nwg_control]
+    #[nwg_events(OnNotice: [App::handle_troubleshoot_notice])]
+    troubleshoot_notice: Notice,
+
+    /// Notice for commands from the control API
+    #[nwg_control]
+    #[nwg_events(OnNotice: [App::handle_control_notice])]
+    control_notice: Notice,
+
     /// Join handle for the connect task
-    connect_task: RefCell<Option<JoinHandle<Result<LookupData, LookupError>>>>,
+    connect_task: RefCell<Option<JoinHandle<(String, Result<LookupData, LookupError>)>>>,
+
+    /// Join handle for the diagnostics task
+    troubleshoot_task: RefCell<Option<JoinHandle<DoctorReport>>>,
 
     /// Http client for sending requests
     http_client: reqwest::Client,
+
+    /// The client config containing the saved profiles
+    config: RefCell<ClientConfig>,
+
+    /// History of recent connection attempts
+    history: RefCell<ConnectionHistory>,
+
+    /// Config loaded by the config watcher task waiting to be applied
+    changed_config: Arc<Mutex<Option<ClientConfig>>>,
+
+    /// Commands from the control API waiting to be handled
+    control_commands: Arc<Mutex<Vec<ControlCommand>>>,
+
+    /// The resolved settings
+    settings: RefCell<Settings>,
 }
 
 impl App {
+    /// Handles a saved profile being picked from the profile picker,
+    /// replaces the connection URL with the profile URL
+    fn handle_profile_selected(&self) {
+        let Some(index) = self.profile_picker.selection() else {
+            return;
+        };
+
+        let config = self.config.borrow();
+        let Some(profile) = config.profiles.get(index) else {
+            return;
+        };
+
+        self.target_url_input.set_text(&profile.connection_url);
+        self.favourite_checkbox
+            .set_check_state(check_state(profile.favourite));
+    }
+
+    /// Handles an entry being picked from the history picker, replaces
+    /// the connection URL with the entry URL
+    fn handle_history_selected(&self) {
+        let Some(index) = self.history_picker.selection() else {
+            return;
+        };
+
+        let url = {
+            let history = self.history.borrow();
+            let Some(entry) = history.entries.get(index) else {
+                return;
+            };
+            entry.url.clone()
+        };
+
+        // Updating the text will update the selected profile
+        self.target_url_input.set_text(&url);
+        self.history_picker.set_selection(None);
+    }
+
+    /// Handles the "Clear history" button being pressed, removes all
+    /// the connection history entries
+    fn handle_clear_history(&self) {
+        {
+            let history = &mut *self.history.borrow_mut();
+            history.clear();
+            write_history_file(history);
+        }
+
+        self.refresh_history();
+    }
+
+    /// Updates the history picker options from the current history
+    fn refresh_history(&self) {
+        let history = self.history.borrow();
+        let labels: Vec<String> = history
+            .entries
+            .iter()
+            .map(|entry| entry.to_string())
+            .collect();
+
+        self.clear_history_button
+            .set_enabled(!labels.is_empty() && !self.settings.borrow().connection_locked);
+        self.history_picker.set_collection(labels);
+    }
+
+    /// Handles the connection URL changing, updates the selected
+    /// profile to match the new URL
+    fn handle_target_changed(&self) {
+        let target = self.target_url_input.text();
+
+        // Handle pasted connection links
+        if !self.settings.borrow().connection_locked && ConnectionLink::is_link(&target) {
+            if let Ok(link) = target.parse::<ConnectionLink>() {
+                self.apply_link(link);
+                return;
+            }
+        }
+
+        let config = self.config.borrow();
+        let selected = config.find_profile(&target);
+
+        if self.profile_picker.selection() != selected {
+            self.profile_picker.set_selection(selected);
+        }
+
+        let favourite = selected
+            .and_then(|index| config.profiles.get(index))
+            .is_some_and(|profile| profile.favourite);
+        self.favourite_checkbox
+            .set_check_state(check_state(favourite));
+        self.favourite_checkbox
+            .set_enabled(selected.is_some() && !self.settings.borrow().connection_locked);
+    }
+
+    /// Saves the profile from a connection `link` and uses its connection
+    /// URL as the target, connecting immediately if requested by the link
+    ///
+    /// ## Arguments
+    /// * `link` - The connection link
+    fn apply_link(&self, link: ConnectionLink) {
+        // Profiles aren't saved when remembering is hidden by the policy
+        if !self.settings.borrow().hide_remember {
+            let config = &mut *self.config.borrow_mut();
+            config.add_profile(&link.url, link.name);
+            config.sort_profiles();
+            write_config_file(config);
+        }
+
+        let labels: Vec<String> = self
+            .config
+            .borrow()
+            .profiles
+            .iter()
+            .map(|profile| profile.to_string())
+            .collect();
+        self.profile_picker.set_collection(labels);
+
+        // Updating the text will update the selected profile
+        self.target_url_input.set_text(&link.url);
+
+        if link.connect {
+            self.handle_set();
+        }
+    }
+
+    /// Handles the "Copy link" button being pressed, copies the connection
+    /// link for the current connection URL to the clipboard
+    fn handle_copy_link(&self) {
+        let target = self.target_url_input.text();
+        let target = target.trim();
+        if target.is_empty() {
+            return;
+        }
+
+        let name = {
+            let config = self.config.borrow();
+            config
+                .find_profile(target)
+                .map(|index| config.profiles[index].name.clone())
+        };
+
+        let link = ConnectionLink::new(target.to_string(), name);
+        Clipboard::set_data_text(&self.window, &link.to_string());
+    }
+
+    /// Handles the favourite checkbox being toggled, updates and
+    /// saves the selected profile
+    fn handle_favourite(&self) {
+        // Profiles aren't saved when remembering is hidden by the policy
+        if self.settings.borrow().hide_remember {
+            return;
+        }
+
+        let favourite = self.favourite_checkbox.check_state() == CheckBoxState::Checked;
+
+        {
+            let config = &mut *self.config.borrow_mut();
+            let Some(index) = self.profile_picker.selection() else {
+                return;
+            };
+            let Some(profile) = config.profiles.get_mut(index) else {
+                return;
+            };
+
+            profile.favourite = favourite;
+            config.sort_profiles();
+            write_config_file(config);
+        }
+
+        self.refresh_profiles();
+    }
+
+    /// Handles the config file changing, updates the saved profiles and
+    /// the connection URL if the URL of its profile changed
+    fn handle_config_notice(&self) {
+        let config = self
+            .changed_config
+            .lock()
+            .ok()
+            .and_then(|mut value| value.take());
+
+        let Some(mut config) = config else { return };
+        config.sort_profiles();
+
+        // Settings are cloned so they aren't borrowed while a message is shown
+        let mut settings = self.settings.borrow().clone();
+        reload_settings(&mut settings, &config, &self.http_client);
+        *self.settings.borrow_mut() = settings;
+
+        let changed_url = {
+            let current = &mut *self.config.borrow_mut();
+
+            // Ignore changes from the client saving the config
+            if config == *current {
+                return;
+            }
+
+            let target = self.target_url_input.text();
+            let changed_url = config
+                .changed_profile_url(current, &target)
+                .map(str::to_string);
+
+            *current = config;
+            changed_url
+        };
+
+        self.refresh_profiles();
+
+        // Target can't be changed when locked by the policy
+        let Some(changed_url) = changed_url.filter(|_| !self.settings.borrow().connection_locked)
+        else {
+            return;
+        };
+
+        self.target_url_input.set_text(&changed_url);
+
+        // Reconnect using the new URL if connected
+        if self.config.borrow().reconnect_on_change && has_server_tasks() {
+            self.handle_set();
+        }
+    }
+
+    /// Updates the profile picker options from the current config
+    /// selecting the profile matching the connection URL
+    fn refresh_profiles(&self) {
+        let config = self.config.borrow();
+        let labels: Vec<String> = config
+            .profiles
+            .iter()
+            .map(|profile| profile.to_string())
+            .collect();
+
+        self.profile_picker.set_collection(labels);
+        drop(config);
+
+        self.handle_target_changed();
+    }
+
+    /// Handles the "Troubleshoot" button being pressed, dispatches a task
+    /// running the diagnostics that will wake up the App with
+    /// `App::handle_troubleshoot_notice` to show the report
+    fn handle_troubleshoot(&self) {
+        if self.troubleshoot_task.borrow().is_some() {
+            return;
+        }
+
+        self.troubleshoot_button.set_enabled(false);
+
+        let sender = self.troubleshoot_notice.sender();
+        let settings = self.settings.borrow();
+        let diagnostics = run_diagnostics(
+            self.http_client.clone(),
+            Some(self.target_url_input.text()),
+            settings.identity_path.clone(),
+            settings.identity_explicit,
+            settings.redirects.clone(),
+            settings.dns_server(),
+        );
+        drop(settings);
+
+        let task = tokio::spawn(async move {
+            let report = diagnostics.await;
+            sender.notice();
+            report
+        });
+
+        *self.troubleshoot_task.borrow_mut() = Some(task);
+    }
+
+    /// Handles the diagnostics complete notice showing the report
+    fn handle_troubleshoot_notice(&self) {
+        let report = self
+            .troubleshoot_task
+            .borrow_mut()
+            .take()
+            // Flatten on the join result
+            .and_then(FutureExt::now_or_never)
+            // Flatten join failure errors (Out of our control)
+            .and_then(Result::ok);
+
+        self.troubleshoot_button.set_enabled(true);
+
+        let Some(report) = report else { return };
+
+        if report.has_failures() {
+            show_warning("Troubleshooting found problems", &report.to_string());
+        } else {
+            show_info("Troubleshooting found no problems", &report.to_string());
+        }
+    }
+
+    /// Handles the commands received from the control API
+    fn handle_control_notice(&self) {
+        let commands = self
+            .control_commands
+            .lock()
+            .map(|mut value| std::mem::take(&mut *value))
+            .unwrap_or_default();
+
+        for command in commands {
+            match command {
+                ControlCommand::Connect(url) => {
+                    // Updating the text will update the selected profile
+                    self.target_url_input.set_text(&url);
+                    self.handle_set();
+                }
+                ControlCommand::Disconnect => {
+                    if let Some(task) = self.connect_task.take() {
+                        task.abort();
+                    }
+
+                    stop_all_servers();
+                    set_connection_status(ConnectionStatus::Disconnected);
+                    self.connection_label.set_text("Not connected");
+                }
+            }
+        }
+    }
+
     /// Handles the "Set" button being pressed, dispatches a connect task
     /// that will wake up the App with `App::handle_connect_notice` to
     /// handle the connection result.
@@ -98,13 +498,16 @@ impl App {
 
         self.connection_label.set_text("Connecting...");
         let target = self.target_url_input.text().to_string();
+        set_connection_status(ConnectionStatus::Connecting {
+            url: target.clone(),
+        });
         let sender = self.connect_notice.sender();
         let http_client = self.http_client.clone();
 
         let task = tokio::spawn(async move {
-            let result = lookup_server(http_client, target).await;
+            let result = lookup_server(http_client, target.clone()).await;
             sender.notice();
-            result
+            (target, result)
         });
 
         *self.connect_task.borrow_mut() = Some(task);
@@ -123,7 +526,24 @@ impl App {
             .and_then(Result::ok);
 
         // Ensure theres actually a result to use
-        let Some(result) = result else { return };
+        let Some((target, result)) = result else {
+            return;
+        };
+
+        // Record the attempt in the connection history, history isn't saved
+        // when remembering is hidden by the policy
+        {
+            let entry = HistoryEntry::new(&target, &result);
+            set_connection_status(ConnectionStatus::from(&entry));
+
+            if !self.settings.borrow().hide_remember {
+                let history = &mut *self.history.borrow_mut();
+                history.push(entry);
+                write_history_file(history);
+            }
+        }
+
+        self.refresh_history();
 
         let mut lookup = match result {
             Ok(value) => value,
@@ -142,14 +562,22 @@ impl App {
         });
 
         // Start the servers
-        start_all_servers(ctx);
+        start_all_servers(ctx, self.settings.borrow().tunnel);
 
         let remember = self.remember_checkbox.check_state() == CheckBoxState::Checked;
 
-        // Save the connection URL
+        // Save the connection URL as a profile
         if remember {
             let connection_url = lookup.url.to_string();
-            write_config_file(ClientConfig { connection_url });
+
+            {
+                let config = &mut *self.config.borrow_mut();
+                config.save_profile(&connection_url);
+                config.sort_profiles();
+                write_config_file(config);
+            }
+
+            self.refresh_profiles();
         }
 
         let text = format!(
@@ -165,9 +593,10 @@ impl App {
 /// Initializes the user interface
 ///
 /// ## Arguments
-/// * `config` - The client config to use
-/// * `client` - The HTTP client to use
-pub fn init(config: Option<ClientConfig>, client: reqwest::Client) {
+/// * `config`   - The client config to use
+/// * `settings` - The resolved settings to use
+/// * `client`   - The HTTP client to use
+pub fn init(config: Option<ClientConfig>, settings: Settings, client: reqwest::Client) {
     // Create tokio async runtime
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
@@ -178,7 +607,7 @@ pub fn init(config: Option<ClientConfig>, client: reqwest::Client) {
     let _enter = runtime.enter();
 
     // Spawn the updating task
-    tokio::spawn(update::update(client.clone()));
+    tokio::spawn(update::update(client.clone(), settings.update));
 
     // Initialize nwg
     nwg_init().expect("Failed to initialize native UI");
@@ -186,18 +615,96 @@ pub fn init(config: Option<ClientConfig>, client: reqwest::Client) {
     // Set the default font family
     Font::set_global_family("Segoe UI").expect("Failed to set default font");
 
+    let remember = config.is_some() && !settings.hide_remember;
+    let mut config = config.unwrap_or_default();
+    config.sort_profiles();
+
+    // Use the provided connection URL or the most recently used profile
+    // as the initial target
+    let target = settings
+        .connection_url
+        .clone()
+        .or_else(|| {
+            config
+                .last_used_profile()
+                .map(|profile| profile.connection_url.clone())
+        })
+        .unwrap_or_default();
+
     // Build the app UI
     let app = App::build_ui(App {
         http_client: client,
+        config: RefCell::new(config),
+        history: RefCell::new(read_history_file()),
+        settings: RefCell::new(settings),
         ..Default::default()
     })
     .expect("Failed to build native UI");
 
-    let (target, remember) = config
-        .map(|value| (value.connection_url, true))
-        .unwrap_or_default();
-
     app.target_url_input.set_text(&target);
+    app.refresh_profiles();
+    app.refresh_history();
+
+    // Grey out the controls locked by the policy
+    if app.settings.borrow().connection_locked {
+        app.target_url_input.set_enabled(false);
+        app.profile_picker.set_enabled(false);
+        app.history_picker.set_enabled(false);
+        app.favourite_checkbox.set_enabled(false);
+    }
+
+    // Spawn the control API forwarding its commands to the app
+    if app.settings.borrow().control_api {
+        let sender = app.control_notice.sender();
+        let control_commands = app.control_commands.clone();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(control::serve(
+            app.settings.borrow().clone(),
+            app.http_client.clone(),
+            tx,
+        ));
+        tokio::spawn(async move {
+            while let Some(command) = rx.recv().await {
+                if let Ok(mut value) = control_commands.lock() {
+                    value.push(command);
+                }
+                sender.notice();
+            }
+        });
+    }
+
+    if app.settings.borrow().hide_remember {
+        app.remember_checkbox.set_visible(false);
+        app.favourite_checkbox.set_visible(false);
+        app.history_picker.set_visible(false);
+        app.clear_history_button.set_visible(false);
+    }
+
+    app.ports_label
+        .set_text(&format!("Local ports: {}", server_ports_text()));
+
+    // Connect immediately when requested by a connection link
+    if app.settings.borrow().auto_connect {
+        app.handle_set();
+    }
+
+    // Spawn the config watching task
+    {
+        let sender = app.config_notice.sender();
+        let changed_config = app.changed_config.clone();
+
+        tokio::spawn(async move {
+            let mut watcher = ConfigWatcher::default();
+            loop {
+                let config = watcher.changed().await;
+                if let Ok(mut value) = changed_config.lock() {
+                    *value = Some(config);
+                }
+                sender.notice();
+            }
+        });
+    }
 
     if remember {
         app.remember_checkbox
@@ -206,3 +713,12 @@ pub fn init(config: Option<ClientConfig>, client: reqwest::Client) {
 
     dispatch_thread_events();
 }
+
+/// Converts a bool into its [`CheckBoxState`] equivalent
+fn check_state(value: bool) -> CheckBoxState {
+    if value {
+        CheckBoxState::Checked
+    } else {
+        CheckBoxState::Unchecked
+    }
+}
@@ -6,11 +6,13 @@ use crate::{
         update::{download_latest_release, get_latest_release},
         Version,
     },
+    settings::UpdateBehavior,
+    shutdown,
     ui::{show_confirm, show_error, show_info},
     APP_VERSION,
 };
 use log::{debug, error};
-use std::{env::current_exe, path::PathBuf, process::exit};
+use std::{env::current_exe, path::PathBuf};
 
 /// The GitHub repository to use for releases
 pub const GITHUB_REPOSITORY: &str = "PocketRelay/Client";
@@ -79,7 +81,11 @@ impl UpdatePaths {
 }
 
 /// Handles the updating process
-pub async fn update(http_client: reqwest::Client) {
+///
+/// ## Arguments
+/// * `http_client` - The HTTP client to use
+/// * `behavior`    - The update behavior to use
+pub async fn update(http_client: reqwest::Client, behavior: UpdateBehavior) {
     let paths = UpdatePaths::default();
 
     // Remove temporary files if they exist
@@ -87,6 +93,11 @@ pub async fn update(http_client: reqwest::Client) {
         error!("Failed to remove temporary files: {}", err);
     }
 
+    if behavior == UpdateBehavior::Disabled {
+        debug!("Update checking is disabled");
+        return;
+    }
+
     debug!("Checking for updates");
 
     let latest_release = match get_latest_release(&http_client, GITHUB_REPOSITORY).await {
@@ -141,7 +152,7 @@ pub async fn update(http_client: reqwest::Client) {
         current_version, latest_version,
     );
 
-    if !show_confirm("New version is available", &msg) {
+    if behavior == UpdateBehavior::Prompt && !show_confirm("New version is available", &msg) {
         return;
     }
 
@@ -177,5 +188,6 @@ pub async fn update(http_client: reqwest::Client) {
         "The client has been updated, restart the client now to use the new version",
     );
 
-    exit(0);
+    // Exit through the shutdown so the hosts entry is removed
+    shutdown::exit(0);
 }